@@ -0,0 +1,81 @@
+// A two-pass blur of a scrolling stripe pattern, as an example of render passes. The pattern is
+// drawn to a buffer, blurred horizontally into a second one and vertically onto the screen.
+// Run with `lively-rs --shader shaders/blur.wgsl` and these passes in the config file:
+//
+// [[passes]]
+// entry_point = "fs_scene"
+// output = "scene"
+//
+// [[passes]]
+// entry_point = "fs_blur_x"
+// output = "blur_x"
+// inputs = ["scene"]
+//
+// [[passes]]
+// entry_point = "fs_main"
+// output = "screen"
+// inputs = ["blur_x"]
+struct Uniforms {
+    mouse: vec2<f32>,
+    time: f32,
+    srgb: f32,
+    resolution: vec2<f32>,
+    imouse: vec4<f32>,
+    loudness: f32,
+    last_key: f32,
+};
+
+@group(0) @binding(0)
+var<uniform> uniforms: Uniforms;
+
+// The pass's only input.
+@group(2) @binding(0)
+var input: texture_2d<f32>;
+@group(2) @binding(1)
+var input_sampler: sampler;
+
+// Pixels between the taps of the blur.
+const SPREAD: f32 = 3.0;
+
+@vertex
+fn vs_main(@builtin(vertex_index) in_vertex_index: u32) -> @builtin(position) vec4<f32> {
+    // A single triangle covering the whole surface.
+    let x = f32(i32(in_vertex_index & 1u) * 4 - 1);
+    let y = f32(i32(in_vertex_index >> 1u) * 4 - 1);
+    return vec4<f32>(x, y, 0.0, 1.0);
+}
+
+@fragment
+fn fs_scene(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let uv = position.xy / uniforms.resolution;
+    // Hard edges, so there is something to blur.
+    let stripes = step(0.5, fract((uv.x + uv.y) * 8.0 + uniforms.time * 0.2));
+    let offset = (uv - uniforms.mouse) * vec2<f32>(uniforms.resolution.x / uniforms.resolution.y, 1.0);
+    let spot = step(length(offset), 0.05);
+    let color = mix(vec3<f32>(0.1, 0.1, 0.2), vec3<f32>(0.9, 0.5, 0.2), stripes);
+    return vec4<f32>(mix(color, vec3<f32>(1.0), spot), 1.0);
+}
+
+// Nine taps of a Gaussian along `direction`, centered on `position`.
+fn blur(position: vec2<f32>, direction: vec2<f32>) -> vec4<f32> {
+    var weights = array<f32, 5>(0.227027, 0.1945946, 0.1216216, 0.054054, 0.016216);
+    let uv = position / uniforms.resolution;
+    let stride = direction * SPREAD / uniforms.resolution;
+    var color = textureSample(input, input_sampler, uv) * weights[0];
+    for (var i = 1; i < 5; i = i + 1) {
+        let offset = stride * f32(i);
+        color += textureSample(input, input_sampler, uv + offset) * weights[i];
+        color += textureSample(input, input_sampler, uv - offset) * weights[i];
+    }
+    return color;
+}
+
+@fragment
+fn fs_blur_x(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    return blur(position.xy, vec2<f32>(1.0, 0.0));
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    return blur(position.xy, vec2<f32>(0.0, 1.0));
+}
@@ -0,0 +1,33 @@
+// A soft glow following the pointer. Reads the uniforms from the fragment stage.
+// Run with `lively-rs --shader shaders/mouse-glow.wgsl`.
+struct Uniforms {
+    mouse: vec2<f32>,
+    time: f32,
+    srgb: f32,
+    resolution: vec2<f32>,
+    imouse: vec4<f32>,
+    loudness: f32,
+    last_key: f32,
+};
+
+@group(0) @binding(0)
+var<uniform> uniforms: Uniforms;
+
+@vertex
+fn vs_main(@builtin(vertex_index) in_vertex_index: u32) -> @builtin(position) vec4<f32> {
+    // A single triangle covering the whole surface.
+    let x = f32(i32(in_vertex_index & 1u) * 4 - 1);
+    let y = f32(i32(in_vertex_index >> 1u) * 4 - 1);
+    return vec4<f32>(x, y, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let uv = position.xy / uniforms.resolution;
+    let aspect = uniforms.resolution.x / uniforms.resolution.y;
+    let offset = (uv - uniforms.mouse) * vec2<f32>(aspect, 1.0);
+    let glow = 0.02 / (dot(offset, offset) + 0.02);
+    let pulse = 0.75 + 0.25 * sin(uniforms.time * 2.0);
+    let background = vec3<f32>(0.05, 0.05, 0.1) * (1.0 - uv.y);
+    return vec4<f32>(background + vec3<f32>(0.3, 0.6, 1.0) * glow * pulse, 1.0);
+}
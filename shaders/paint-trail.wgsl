@@ -0,0 +1,40 @@
+// Paints with the pointer: every frame fades the previous one by 2% and stamps a dot where the
+// pointer is, leaving a fading trail. Run with `lively-rs --shader shaders/paint-trail.wgsl`.
+struct Uniforms {
+    mouse: vec2<f32>,
+    time: f32,
+    srgb: f32,
+    resolution: vec2<f32>,
+    imouse: vec4<f32>,
+    loudness: f32,
+    last_key: f32,
+};
+
+@group(0) @binding(0)
+var<uniform> uniforms: Uniforms;
+
+// What was on screen last frame, black on the first one and after a resize.
+@group(3) @binding(0)
+var iPrevFrame: texture_2d<f32>;
+@group(3) @binding(1)
+var iPrevFrame_sampler: sampler;
+
+@vertex
+fn vs_main(@builtin(vertex_index) in_vertex_index: u32) -> @builtin(position) vec4<f32> {
+    // A single triangle covering the whole surface.
+    let x = f32(i32(in_vertex_index & 1u) * 4 - 1);
+    let y = f32(i32(in_vertex_index >> 1u) * 4 - 1);
+    return vec4<f32>(x, y, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let uv = position.xy / uniforms.resolution;
+    let previous = textureSample(iPrevFrame, iPrevFrame_sampler, uv).rgb * 0.98;
+    let aspect = uniforms.resolution.x / uniforms.resolution.y;
+    let offset = (uv - uniforms.mouse) * vec2<f32>(aspect, 1.0);
+    let brush = smoothstep(0.02, 0.015, length(offset));
+    // The paint slowly cycles through the hues.
+    let paint = 0.5 + 0.5 * cos(uniforms.time + vec3<f32>(0.0, 2.0, 4.0));
+    return vec4<f32>(mix(previous, paint, brush), 1.0);
+}
@@ -0,0 +1,283 @@
+// Audio capture for music-reactive shaders, behind the `audio` feature. A worker thread records
+// the system's audio output (a monitor source when there is one), and fills a Shadertoy-style
+// 512x2 channel texture: the spectrum in row 0 and the waveform in row 1.
+use crate::config::ChannelFilter;
+use crate::graphics::channels::ChannelImage;
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use rustfft::num_complex::Complex;
+use std::collections::VecDeque;
+use std::num::NonZeroU32;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Width of the audio texture, the number of spectrum bins and waveform samples.
+pub const AUDIO_TEXTURE_WIDTH: u32 = 512;
+const FFT_SIZE: usize = 2 * AUDIO_TEXTURE_WIDTH as usize;
+/// How often the spectrum is recomputed.
+const ANALYSIS_INTERVAL: Duration = Duration::from_millis(16);
+/// How long to wait before capturing again after the device went away.
+const RETRY_INTERVAL: Duration = Duration::from_secs(1);
+// Like the Web Audio analyser Shadertoy uses: decibel range mapped to 0..1, and how much of the
+// previous spectrum is kept each time.
+const MIN_DECIBELS: f32 = -100.0;
+const MAX_DECIBELS: f32 = -30.0;
+const SMOOTHING: f32 = 0.8;
+
+struct Analysis {
+    /// RGBA pixels of the audio texture.
+    pixels: Vec<u8>,
+    /// RMS of the last window of samples, 0..1.
+    loudness: f32,
+}
+
+impl Analysis {
+    fn silent() -> Self {
+        Self {
+            pixels: vec![0; 4 * 2 * AUDIO_TEXTURE_WIDTH as usize],
+            loudness: 0.0,
+        }
+    }
+}
+
+pub struct AudioCapture {
+    analysis: Arc<Mutex<Analysis>>,
+}
+
+impl AudioCapture {
+    /// Starts capturing on a worker thread. Capture problems are logged and retried there.
+    pub fn start() -> Self {
+        let analysis = Arc::new(Mutex::new(Analysis::silent()));
+        let shared = analysis.clone();
+        thread::spawn(move || loop {
+            if let Err(err) = capture(&shared) {
+                log::warn!("Audio capture stopped: {}", err);
+            }
+            thread::sleep(RETRY_INTERVAL);
+        });
+        Self { analysis }
+    }
+
+    /// The texture the capture is uploaded to, silent until the first upload.
+    pub fn image() -> ChannelImage {
+        ChannelImage::blank(AUDIO_TEXTURE_WIDTH, 2, ChannelFilter::Linear)
+    }
+
+    /// Uploads the latest spectrum and waveform to `texture`, made from [`AudioCapture::image`],
+    /// and returns the loudness.
+    pub fn upload(&self, queue: &wgpu::Queue, texture: &wgpu::Texture) -> f32 {
+        let analysis = self.analysis.lock().unwrap();
+        queue.write_texture(
+            texture.as_image_copy(),
+            &analysis.pixels,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: NonZeroU32::new(4 * AUDIO_TEXTURE_WIDTH),
+                rows_per_image: None,
+            },
+            wgpu::Extent3d {
+                width: AUDIO_TEXTURE_WIDTH,
+                height: 2,
+                depth_or_array_layers: 1,
+            },
+        );
+        analysis.loudness
+    }
+}
+
+/// Captures and analyses audio until the stream fails, e.g. because the device was unplugged.
+fn capture(analysis: &Mutex<Analysis>) -> Result<(), String> {
+    let host = cpal::default_host();
+    // The monitor source of the default sink is what's playing; fall back to the microphone.
+    let device = host
+        .input_devices()
+        .map_err(|err| err.to_string())?
+        .find(|device| device.name().is_ok_and(|name| name.contains("monitor")))
+        .or_else(|| host.default_input_device())
+        .ok_or("no audio input device")?;
+    let config = device
+        .default_input_config()
+        .map_err(|err| err.to_string())?;
+    let channels = config.channels() as usize;
+    let samples = Arc::new(Mutex::new(VecDeque::with_capacity(FFT_SIZE)));
+    let failed = Arc::new(AtomicBool::new(false));
+    let on_error = {
+        let failed = failed.clone();
+        move |err| {
+            log::warn!("Audio stream error: {}", err);
+            failed.store(true, Ordering::Relaxed);
+        }
+    };
+    let stream = match config.sample_format() {
+        cpal::SampleFormat::F32 => {
+            let samples = samples.clone();
+            device.build_input_stream(
+                &config.config(),
+                move |data: &[f32], _: &_| push(&samples, data, channels, |sample| sample),
+                on_error,
+                None,
+            )
+        }
+        cpal::SampleFormat::I16 => {
+            let samples = samples.clone();
+            device.build_input_stream(
+                &config.config(),
+                move |data: &[i16], _: &_| {
+                    push(&samples, data, channels, |sample| {
+                        sample as f32 / i16::MAX as f32
+                    })
+                },
+                on_error,
+                None,
+            )
+        }
+        format => return Err(format!("unsupported sample format {format:?}")),
+    }
+    .map_err(|err| err.to_string())?;
+    stream.play().map_err(|err| err.to_string())?;
+    log::info!("Capturing audio from {}", device.name().unwrap_or_default());
+
+    let mut analyser = Analyser::new();
+    while !failed.load(Ordering::Relaxed) {
+        thread::sleep(ANALYSIS_INTERVAL);
+        let window: Vec<f32> = {
+            let samples = samples.lock().unwrap();
+            if samples.len() < FFT_SIZE {
+                continue;
+            }
+            samples.iter().copied().collect()
+        };
+        analyser.analyse(&window, &mut analysis.lock().unwrap());
+    }
+    Err("the audio device went away".to_string())
+}
+
+/// Turns windows of samples into the audio texture, keeping the smoothed spectrum in between.
+struct Analyser {
+    fft: Arc<dyn rustfft::Fft<f32>>,
+    spectrum: Vec<f32>,
+    buffer: Vec<Complex<f32>>,
+}
+
+impl Analyser {
+    fn new() -> Self {
+        Self {
+            fft: rustfft::FftPlanner::new().plan_fft_forward(FFT_SIZE),
+            spectrum: vec![0.0; AUDIO_TEXTURE_WIDTH as usize],
+            buffer: vec![Complex::default(); FFT_SIZE],
+        }
+    }
+
+    /// Analyses the last [`FFT_SIZE`] samples into the pixels and loudness of `analysis`.
+    fn analyse(&mut self, window: &[f32], analysis: &mut Analysis) {
+        // Hann window, so the edges of the window don't show up as frequencies.
+        for (i, (value, sample)) in self.buffer.iter_mut().zip(window).enumerate() {
+            let weight = 0.5 - 0.5 * (std::f32::consts::TAU * i as f32 / FFT_SIZE as f32).cos();
+            *value = Complex::new(sample * weight, 0.0);
+        }
+        self.fft.process(&mut self.buffer);
+        for (bin, value) in self.spectrum.iter_mut().zip(&self.buffer) {
+            *bin = SMOOTHING * *bin + (1.0 - SMOOTHING) * value.norm() / FFT_SIZE as f32;
+        }
+        let loudness =
+            (window.iter().map(|sample| sample * sample).sum::<f32>() / FFT_SIZE as f32).sqrt();
+
+        let (spectrum_row, waveform_row) = analysis
+            .pixels
+            .split_at_mut(4 * AUDIO_TEXTURE_WIDTH as usize);
+        for (pixel, magnitude) in spectrum_row.chunks_exact_mut(4).zip(&self.spectrum) {
+            let decibels = 20.0 * magnitude.max(f32::MIN_POSITIVE).log10();
+            let value = (decibels - MIN_DECIBELS) / (MAX_DECIBELS - MIN_DECIBELS);
+            set_pixel(pixel, value);
+        }
+        let latest = &window[FFT_SIZE - AUDIO_TEXTURE_WIDTH as usize..];
+        for (pixel, sample) in waveform_row.chunks_exact_mut(4).zip(latest) {
+            set_pixel(pixel, 0.5 + 0.5 * sample);
+        }
+        analysis.loudness = loudness.min(1.0);
+    }
+}
+
+/// Mixes interleaved frames down to mono and keeps the last [`FFT_SIZE`] samples.
+fn push<T: Copy>(
+    samples: &Mutex<VecDeque<f32>>,
+    data: &[T],
+    channels: usize,
+    to_f32: impl Fn(T) -> f32,
+) {
+    let mut samples = samples.lock().unwrap();
+    for frame in data.chunks_exact(channels) {
+        if samples.len() == FFT_SIZE {
+            samples.pop_front();
+        }
+        let sum: f32 = frame.iter().map(|&sample| to_f32(sample)).sum();
+        samples.push_back(sum / channels as f32);
+    }
+}
+
+fn set_pixel(pixel: &mut [u8], value: f32) {
+    let value = (value.clamp(0.0, 1.0) * 255.0) as u8;
+    pixel.copy_from_slice(&[value, value, value, 255]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WIDTH: usize = AUDIO_TEXTURE_WIDTH as usize;
+
+    #[test]
+    fn push_mixes_down_to_mono() {
+        let samples = Mutex::new(VecDeque::new());
+        push(&samples, &[1.0, 0.0, 0.5, 0.5, -1.0, 0.0], 2, |sample| {
+            sample
+        });
+        assert_eq!(*samples.lock().unwrap(), [0.5, 0.5, -0.5]);
+        // A trailing partial frame is dropped.
+        let samples = Mutex::new(VecDeque::new());
+        push(&samples, &[i16::MAX, i16::MAX, 0], 2, |sample| {
+            sample as f32 / i16::MAX as f32
+        });
+        assert_eq!(*samples.lock().unwrap(), [1.0]);
+    }
+
+    #[test]
+    fn push_keeps_the_last_fft_size_samples() {
+        let samples = Mutex::new(VecDeque::new());
+        let data: Vec<f32> = (0..FFT_SIZE * 3 / 2).map(|i| i as f32).collect();
+        push(&samples, &data, 1, |sample| sample);
+        let samples = samples.lock().unwrap();
+        assert_eq!(samples.len(), FFT_SIZE);
+        assert_eq!(samples.front(), Some(&((FFT_SIZE / 2) as f32)));
+        assert_eq!(samples.back(), Some(&((FFT_SIZE * 3 / 2 - 1) as f32)));
+    }
+
+    #[test]
+    fn sine_wave_fills_the_spectrum_and_waveform_rows() {
+        // A full-scale sine that falls exactly on spectrum bin 40.
+        const BIN: usize = 40;
+        let window: Vec<f32> = (0..FFT_SIZE)
+            .map(|i| (std::f32::consts::TAU * (BIN * i) as f32 / FFT_SIZE as f32).sin())
+            .collect();
+        let mut analysis = Analysis::silent();
+        Analyser::new().analyse(&window, &mut analysis);
+
+        assert_eq!(analysis.pixels.len(), 4 * WIDTH * 2);
+        let (spectrum, waveform) = analysis.pixels.split_at(4 * WIDTH);
+        // Grey pixels, opaque.
+        for pixel in analysis.pixels.chunks_exact(4) {
+            assert_eq!([pixel[1], pixel[2], pixel[3]], [pixel[0], pixel[0], 255]);
+        }
+        let loudest = (0..WIDTH).max_by_key(|&bin| spectrum[4 * bin]).unwrap();
+        assert_eq!(loudest, BIN);
+        assert!(spectrum[4 * BIN] > 200);
+        assert!(spectrum[4 * (BIN + 20)] < 50);
+        // Row 1 is the latest half of the window, mapped from -1..1 to 0..255.
+        for (x, pixel) in waveform.chunks_exact(4).enumerate() {
+            let expected = (0.5 + 0.5 * window[FFT_SIZE - WIDTH + x]) * 255.0;
+            assert!((pixel[0] as f32 - expected).abs() <= 1.0, "sample {x}");
+        }
+        assert!((analysis.loudness - std::f32::consts::FRAC_1_SQRT_2).abs() < 0.01);
+    }
+}
@@ -0,0 +1,167 @@
+// Command line flags. They override the matching settings from the config file.
+use clap::Parser;
+use lively_rs::config::{
+    Config, Edge, KeyboardMode, LayerKind, PresentModeKind, Scaling, CHANNEL_COUNT,
+};
+use std::path::PathBuf;
+
+#[derive(Debug, Parser)]
+#[command(version, about = "Animated shader wallpapers for wlroots compositors")]
+pub struct Cli {
+    /// WGSL or GLSL (.frag, .glsl) shader to render instead of the built-in one; reloaded when
+    /// it changes.
+    #[arg(long, value_name = "PATH")]
+    pub shader: Option<PathBuf>,
+    /// Shadertoy image shader (GLSL with mainImage, iTime, iResolution and iMouse) to render
+    /// instead of a WGSL one.
+    #[arg(long, value_name = "PATH", conflicts_with = "shader")]
+    pub shadertoy: Option<PathBuf>,
+    /// Show an image, or an animated GIF or APNG, instead of a shader.
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["shader", "shadertoy"])]
+    pub image: Option<PathBuf>,
+    /// How the image is fitted to the output.
+    #[arg(long, value_enum)]
+    pub scaling: Option<Scaling>,
+    /// Output to put the wallpaper on, by connector name (e.g. DP-2) or description. Can be
+    /// repeated; the wallpaper waits for the output if it isn't connected yet.
+    #[arg(long = "output", value_name = "NAME")]
+    pub outputs: Vec<String>,
+    /// Frame-rate cap, 0 follows the compositor's frame callbacks.
+    #[arg(long, value_name = "N")]
+    pub fps: Option<u32>,
+    /// Layer-shell layer to place the wallpaper on.
+    #[arg(long, value_enum)]
+    pub layer: Option<LayerKind>,
+    /// Edges to anchor the layer to, e.g. top,right for the top-right corner.
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub anchor: Vec<Edge>,
+    /// Distance from the anchored edges, one value for all of them or top,right,bottom,left.
+    #[arg(long, value_name = "PX", value_parser = parse_margin)]
+    pub margin: Option<[i32; 4]>,
+    /// Size of the layer as WIDTHxHEIGHT; 0 stretches between the anchors on that axis.
+    #[arg(long, value_name = "WxH", value_parser = parse_size)]
+    pub size: Option<[u32; 2]>,
+    /// Space to reserve from other surfaces, -1 to cover the whole output.
+    #[arg(long, value_name = "PX", allow_negative_numbers = true)]
+    pub exclusive_zone: Option<i32>,
+    /// Whether the layer takes keyboard focus.
+    #[arg(long, value_enum)]
+    pub keyboard_interactivity: Option<KeyboardMode>,
+    /// Bind the keys held down to iChannelN as a 256x1 texture indexed by evdev key code, and
+    /// the time of the last key press to the last_key uniform. This listens to every keystroke,
+    /// so it is off by default; shaders never see what was typed.
+    #[arg(long, value_name = "N", value_parser = parse_channel)]
+    pub keyboard_input: Option<usize>,
+    /// How frames are queued for display. Falls back to a supported mode if unavailable.
+    #[arg(long, value_enum)]
+    pub present_mode: Option<PresentModeKind>,
+    /// Keep rendering while a fullscreen window covers the wallpaper.
+    #[arg(long)]
+    pub no_pause_on_fullscreen: bool,
+    /// Stop rendering to outputs that are powered off. This takes their power control from
+    /// tools like wlopm and idle daemons.
+    #[arg(long)]
+    pub watch_output_power: bool,
+    /// Print the outputs the compositor announces and exit.
+    #[arg(long)]
+    pub list_outputs: bool,
+    /// Write frame timing spans to FILE as a Chrome trace on exit.
+    #[arg(long, value_name = "FILE")]
+    pub tracing_chrome: Option<PathBuf>,
+}
+
+impl Cli {
+    /// Overrides the settings in `config` with the flags that were given.
+    pub fn apply(&self, config: &mut Config) {
+        if let Some(shader) = &self.shader {
+            config.shader = Some(shader.clone());
+            config.shadertoy = false;
+        }
+        if let Some(shader) = &self.shadertoy {
+            config.shader = Some(shader.clone());
+            config.shadertoy = true;
+        }
+        if let Some(image) = &self.image {
+            config.image = Some(image.clone());
+        }
+        if self.shader.is_some() || self.shadertoy.is_some() {
+            config.image = None;
+        }
+        if let Some(scaling) = self.scaling {
+            config.scaling = scaling;
+        }
+        if !self.outputs.is_empty() {
+            config.outputs = self.outputs.clone();
+        }
+        if let Some(fps) = self.fps {
+            config.fps = fps;
+        }
+        if let Some(layer) = self.layer {
+            config.layer.layer = layer;
+        }
+        if !self.anchor.is_empty() {
+            config.layer.anchor = self.anchor.clone();
+        }
+        if let Some(margin) = self.margin {
+            config.layer.margin = margin;
+        }
+        if let Some(size) = self.size {
+            config.layer.size = size;
+        }
+        if let Some(exclusive_zone) = self.exclusive_zone {
+            config.layer.exclusive_zone = exclusive_zone;
+        }
+        if let Some(keyboard_interactivity) = self.keyboard_interactivity {
+            config.layer.keyboard_interactivity = keyboard_interactivity;
+        }
+        if let Some(channel) = self.keyboard_input {
+            config.keyboard_channel = Some(channel);
+        }
+        if let Some(present_mode) = self.present_mode {
+            config.present_mode = present_mode;
+        }
+        if self.no_pause_on_fullscreen {
+            config.pause_on_fullscreen = false;
+        }
+        if self.watch_output_power {
+            config.watch_output_power = true;
+        }
+    }
+}
+
+fn parse_margin(value: &str) -> Result<[i32; 4], String> {
+    let margins = value
+        .split(',')
+        .map(|margin| margin.trim().parse())
+        .collect::<Result<Vec<i32>, _>>()
+        .map_err(|err| err.to_string())?;
+    match margins[..] {
+        [all] => Ok([all; 4]),
+        [top, right, bottom, left] => Ok([top, right, bottom, left]),
+        _ => Err("expected one margin or four (top,right,bottom,left)".to_string()),
+    }
+}
+
+fn parse_channel(value: &str) -> Result<usize, String> {
+    match value.parse() {
+        Ok(channel) if channel < CHANNEL_COUNT => Ok(channel),
+        _ => Err(format!(
+            "expected a channel from 0 to {}",
+            CHANNEL_COUNT - 1
+        )),
+    }
+}
+
+fn parse_size(value: &str) -> Result<[u32; 2], String> {
+    let (width, height) = value
+        .split_once('x')
+        .ok_or("expected WIDTHxHEIGHT, e.g. 400x300")?;
+    Ok([
+        width
+            .parse()
+            .map_err(|err| format!("invalid width: {err}"))?,
+        height
+            .parse()
+            .map_err(|err| format!("invalid height: {err}"))?,
+    ])
+}
@@ -0,0 +1,31 @@
+// The lively-rs command: reads the config file, applies the flags on top and runs the wallpaper.
+mod cli;
+
+use clap::Parser;
+use lively_rs::{Config, LivelyError, Settings};
+
+fn main() {
+    lively_rs::mark_startup();
+    let cli = cli::Cli::parse();
+    env_logger::init();
+    if let Err(err) = run(cli) {
+        eprintln!("lively-rs: {err}");
+        std::process::exit(err.exit_code());
+    }
+}
+
+fn run(cli: cli::Cli) -> Result<(), LivelyError> {
+    // Listing outputs doesn't need a valid config.
+    let config = if cli.list_outputs {
+        Config::default()
+    } else {
+        let mut config = Config::load().map_err(LivelyError::Config)?;
+        cli.apply(&mut config);
+        config
+    };
+    lively_rs::run(Settings {
+        config,
+        list_outputs: cli.list_outputs,
+        tracing_chrome: cli.tracing_chrome,
+    })
+}
@@ -0,0 +1,17 @@
+// Copies the final frame from its buffer to the swapchain, when the shader samples iPrevFrame.
+@group(0) @binding(0)
+var frame: texture_2d<f32>;
+
+@vertex
+fn vs_main(@builtin(vertex_index) in_vertex_index: u32) -> @builtin(position) vec4<f32> {
+    // A single triangle covering the whole surface.
+    let x = f32(i32(in_vertex_index & 1u) * 4 - 1);
+    let y = f32(i32(in_vertex_index >> 1u) * 4 - 1);
+    return vec4<f32>(x, y, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    // The buffer has the size of the swapchain, so texels map one to one.
+    return textureLoad(frame, vec2<i32>(position.xy), 0);
+}
@@ -0,0 +1,437 @@
+// Configuration file, read from $XDG_CONFIG_HOME/lively-rs/config.toml.
+//
+// shader = "~/.config/lively-rs/plasma.wgsl"
+// shadertoy = false
+// image = "~/pictures/wallpaper.png"
+// scaling = "fill"
+// outputs = ["DP-2"]
+// fps = 0
+// pause_on_fullscreen = true
+// watch_output_power = false
+// present_mode = "fifo"
+// srgb = true
+// clear_color = [0.0, 0.0, 0.0, 1.0]
+// audio_channel = 0
+//
+// [battery]
+// policy = "throttle"
+// fps = 10
+//
+// [layer]
+// layer = "background"
+// anchor = ["top", "bottom", "left", "right"]
+// margin = [0, 0, 0, 0]
+// size = [0, 0]
+// exclusive_zone = -1
+// keyboard_interactivity = "none"
+//
+// [[channels]]
+// path = "~/pictures/noise.png"
+// wrap = "repeat"
+// filter = "linear"
+// vflip = false
+//
+// [[passes]]
+// shader = "~/.config/lively-rs/blur.wgsl"
+// entry_point = "fs_blur_x"
+// output = "blur_x"
+// inputs = ["scene"]
+pub use crate::graphics::channels::CHANNEL_COUNT;
+use serde::Deserialize;
+use smithay_client_toolkit::shell::wlr_layer::{Anchor, KeyboardInteractivity, Layer};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Shader to render, WGSL or GLSL (.frag, .glsl); the embedded one when unset.
+    pub shader: Option<PathBuf>,
+    /// The shader is a Shadertoy image shader (GLSL with `mainImage`, `iTime`, ...).
+    pub shadertoy: bool,
+    /// Image to show instead of a shader, drawn only when the surface changes or, for animated
+    /// GIFs and APNGs, when the next frame is due.
+    pub image: Option<PathBuf>,
+    /// How the image is fitted to the output; `clear_color` fills the rest.
+    pub scaling: Scaling,
+    /// Names or descriptions of the outputs to put the wallpaper on, every output when empty.
+    pub outputs: Vec<String>,
+    /// Frame-rate cap, 0 follows the compositor's frame callbacks.
+    pub fps: u32,
+    /// Stop rendering to an output while a focused fullscreen window covers it.
+    pub pause_on_fullscreen: bool,
+    /// Stop rendering to outputs that are powered off by watching wlr-output-power-management.
+    /// Off by default: holding an output's power control object keeps wlopm and idle daemons
+    /// from turning it off. Without it, compositors simply stop sending frame callbacks.
+    pub watch_output_power: bool,
+    /// How frames are queued for display; falls back to a supported mode if unavailable.
+    pub present_mode: PresentModeKind,
+    /// Prefer an sRGB surface format; false takes whatever format the driver lists first.
+    pub srgb: bool,
+    /// Color behind the shader's output as premultiplied RGBA in 0..1. Together with shader
+    /// alpha, a transparent one lets the layers below show through.
+    pub clear_color: [f64; 4],
+    pub layer: LayerConfig,
+    pub battery: BatteryConfig,
+    /// Images shaders can sample as `iChannel0` to `iChannel3`, in that order.
+    pub channels: Vec<ChannelConfig>,
+    /// Channel to put the audio spectrum and waveform in instead of an image, with the `audio`
+    /// feature.
+    pub audio_channel: Option<usize>,
+    /// Channel to put the key states in, only set by `--keyboard-input` so that listening to
+    /// keystrokes is never turned on by a config file.
+    #[serde(skip)]
+    pub keyboard_channel: Option<usize>,
+    /// Render passes run in order every frame, the last one drawing to the screen. Without
+    /// any, the shader's `fs_main` draws straight to the screen.
+    pub passes: Vec<PassConfig>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            shader: None,
+            shadertoy: false,
+            image: None,
+            scaling: Scaling::Fill,
+            outputs: Vec::new(),
+            fps: 0,
+            pause_on_fullscreen: true,
+            watch_output_power: false,
+            present_mode: PresentModeKind::Fifo,
+            srgb: true,
+            clear_color: [0.0, 0.0, 0.0, 1.0],
+            layer: LayerConfig::default(),
+            battery: BatteryConfig::default(),
+            channels: Vec::new(),
+            audio_channel: None,
+            keyboard_channel: None,
+            passes: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LayerConfig {
+    pub layer: LayerKind,
+    pub anchor: Vec<Edge>,
+    /// Margins from the anchored edges, as [top, right, bottom, left].
+    pub margin: [i32; 4],
+    /// Size as [width, height]; 0 stretches between the anchors on that axis.
+    pub size: [u32; 2],
+    /// Space reserved from other surfaces, -1 to ignore other layers' zones and cover the output.
+    pub exclusive_zone: i32,
+    pub keyboard_interactivity: KeyboardMode,
+}
+
+impl Default for LayerConfig {
+    fn default() -> Self {
+        Self {
+            layer: LayerKind::Background,
+            anchor: vec![Edge::Top, Edge::Bottom, Edge::Left, Edge::Right],
+            margin: [0; 4],
+            size: [0; 2],
+            exclusive_zone: -1,
+            keyboard_interactivity: KeyboardMode::None,
+        }
+    }
+}
+
+impl LayerConfig {
+    pub fn anchor(&self) -> Anchor {
+        self.anchor
+            .iter()
+            .fold(Anchor::empty(), |anchor, edge| anchor | edge.anchor())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BatteryConfig {
+    /// What to do while the system runs on battery.
+    pub policy: BatteryPolicy,
+    /// Frame-rate cap on battery with the `throttle` policy.
+    pub fps: u32,
+}
+
+impl Default for BatteryConfig {
+    fn default() -> Self {
+        Self {
+            policy: BatteryPolicy::Ignore,
+            fps: 10,
+        }
+    }
+}
+
+/// Scaling modes of the image mode. The values are what the built-in image shader switches on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Scaling {
+    /// Cover the output, cropping the image.
+    Fill = 0,
+    /// Show the whole image, letterboxed.
+    Fit = 1,
+    /// Unscaled, in the middle of the output.
+    Center = 2,
+    /// Stretch to the output, ignoring the aspect ratio.
+    Stretch = 3,
+    /// Repeat unscaled from the top left corner.
+    Tile = 4,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ChannelConfig {
+    pub path: PathBuf,
+    #[serde(default)]
+    pub wrap: ChannelWrap,
+    #[serde(default)]
+    pub filter: ChannelFilter,
+    /// Flip the image vertically, like Shadertoy's vflip, for shaders with a bottom-left origin.
+    #[serde(default)]
+    pub vflip: bool,
+}
+
+/// The `output` of the pass that draws to the screen.
+pub const SCREEN: &str = "screen";
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PassConfig {
+    /// Shader file with the pass's entry point, the main shader when unset.
+    #[serde(default)]
+    pub shader: Option<PathBuf>,
+    /// Fragment entry point the pass draws with.
+    #[serde(default = "default_entry_point")]
+    pub entry_point: String,
+    /// Buffer to render to, or `screen` for the last pass.
+    pub output: String,
+    /// Buffers the pass samples, bound in group 2 in this order. A buffer not rendered yet this
+    /// frame, such as the pass's own output, holds the previous frame.
+    #[serde(default)]
+    pub inputs: Vec<String>,
+}
+
+fn default_entry_point() -> String {
+    "fs_main".to_string()
+}
+
+/// What a channel samples outside 0..1.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChannelWrap {
+    #[default]
+    Repeat,
+    Clamp,
+    Mirror,
+}
+
+impl From<ChannelWrap> for wgpu::AddressMode {
+    fn from(wrap: ChannelWrap) -> Self {
+        match wrap {
+            ChannelWrap::Repeat => wgpu::AddressMode::Repeat,
+            ChannelWrap::Clamp => wgpu::AddressMode::ClampToEdge,
+            ChannelWrap::Mirror => wgpu::AddressMode::MirrorRepeat,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChannelFilter {
+    #[default]
+    Linear,
+    Nearest,
+}
+
+impl From<ChannelFilter> for wgpu::FilterMode {
+    fn from(filter: ChannelFilter) -> Self {
+        match filter {
+            ChannelFilter::Linear => wgpu::FilterMode::Linear,
+            ChannelFilter::Nearest => wgpu::FilterMode::Nearest,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BatteryPolicy {
+    /// Render the same as on AC power.
+    Ignore,
+    /// Drop to the battery frame-rate cap.
+    Throttle,
+    /// Stop rendering, leaving the last frame on screen.
+    Pause,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LayerKind {
+    Background,
+    Bottom,
+    Top,
+    Overlay,
+}
+
+impl From<LayerKind> for Layer {
+    fn from(kind: LayerKind) -> Self {
+        match kind {
+            LayerKind::Background => Layer::Background,
+            LayerKind::Bottom => Layer::Bottom,
+            LayerKind::Top => Layer::Top,
+            LayerKind::Overlay => Layer::Overlay,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum PresentModeKind {
+    /// Wait for the compositor; never tears and uses the least power.
+    Fifo,
+    /// Replace the queued frame with newer ones.
+    Mailbox,
+    /// Present right away, for benchmarking only.
+    Immediate,
+}
+
+impl PresentModeKind {
+    /// Picks this mode if the surface supports it, otherwise the best supported fallback.
+    /// Immediate is never picked unless asked for.
+    pub fn choose(self, supported: &[wgpu::PresentMode]) -> wgpu::PresentMode {
+        let preferred = match self {
+            PresentModeKind::Fifo => wgpu::PresentMode::Fifo,
+            PresentModeKind::Mailbox => wgpu::PresentMode::Mailbox,
+            PresentModeKind::Immediate => wgpu::PresentMode::Immediate,
+        };
+        [
+            preferred,
+            wgpu::PresentMode::Fifo,
+            wgpu::PresentMode::Mailbox,
+        ]
+        .into_iter()
+        .find(|mode| supported.contains(mode))
+        // Fifo support is required of every surface, even if a driver forgets to list it.
+        .unwrap_or(wgpu::PresentMode::Fifo)
+    }
+}
+
+/// Whether the layer takes keyboard focus. A background wallpaper shouldn't, or it can steal
+/// focus from windows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum KeyboardMode {
+    None,
+    Exclusive,
+    OnDemand,
+}
+
+impl From<KeyboardMode> for KeyboardInteractivity {
+    fn from(mode: KeyboardMode) -> Self {
+        match mode {
+            KeyboardMode::None => KeyboardInteractivity::None,
+            KeyboardMode::Exclusive => KeyboardInteractivity::Exclusive,
+            KeyboardMode::OnDemand => KeyboardInteractivity::OnDemand,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Edge {
+    Top,
+    Bottom,
+    Left,
+    Right,
+}
+
+impl Edge {
+    fn anchor(self) -> Anchor {
+        match self {
+            Edge::Top => Anchor::TOP,
+            Edge::Bottom => Anchor::BOTTOM,
+            Edge::Left => Anchor::LEFT,
+            Edge::Right => Anchor::RIGHT,
+        }
+    }
+}
+
+impl Config {
+    /// Loads the config file, falling back to the defaults when there is none.
+    pub fn load() -> Result<Self, String> {
+        let Some(path) = config_path() else {
+            return Ok(Self::default());
+        };
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(format!("Failed to read {}: {}", path.display(), err)),
+        };
+        let mut config: Config = toml::from_str(&text)
+            .map_err(|err| format!("Invalid config {}: {}", path.display(), err))?;
+        config.shader = config.shader.map(|shader| expand_home(&shader));
+        config.image = config.image.map(|image| expand_home(&image));
+        if config.channels.len() > CHANNEL_COUNT {
+            return Err(format!(
+                "Invalid config {}: at most {} channels are supported",
+                path.display(),
+                CHANNEL_COUNT
+            ));
+        }
+        if config
+            .audio_channel
+            .is_some_and(|channel| channel >= CHANNEL_COUNT)
+        {
+            return Err(format!(
+                "Invalid config {}: audio_channel must be below {}",
+                path.display(),
+                CHANNEL_COUNT
+            ));
+        }
+        for channel in &mut config.channels {
+            channel.path = expand_home(&channel.path);
+        }
+        check_passes(&config.passes)
+            .map_err(|err| format!("Invalid config {}: {}", path.display(), err))?;
+        for pass in &mut config.passes {
+            pass.shader = pass.shader.as_deref().map(expand_home);
+        }
+        log::info!("Loaded config from {}", path.display());
+        Ok(config)
+    }
+}
+
+/// Checks that only the last pass draws to the screen and that every input is a buffer some
+/// pass renders to.
+fn check_passes(passes: &[PassConfig]) -> Result<(), String> {
+    let Some((last, rest)) = passes.split_last() else {
+        return Ok(());
+    };
+    if last.output != SCREEN {
+        return Err(format!("the last pass must have output = \"{SCREEN}\""));
+    }
+    if rest.iter().any(|pass| pass.output == SCREEN) {
+        return Err("only the last pass can draw to the screen".to_string());
+    }
+    for input in passes.iter().flat_map(|pass| &pass.inputs) {
+        if !rest.iter().any(|pass| pass.output == *input) {
+            return Err(format!("no pass renders to the input buffer {input:?}"));
+        }
+    }
+    Ok(())
+}
+
+fn config_path() -> Option<PathBuf> {
+    let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(config_dir.join("lively-rs").join("config.toml"))
+}
+
+/// Expands a leading `~` in paths from the config file, which the shell doesn't do for us.
+fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), std::env::var_os("HOME")) {
+        (Ok(rest), Some(home)) => Path::new(&home).join(rest),
+        _ => path.to_path_buf(),
+    }
+}
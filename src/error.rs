@@ -0,0 +1,37 @@
+use thiserror::Error;
+
+/// Failures that stop lively-rs, each with its own exit code so scripts and service managers
+/// can tell them apart. 2 is left to argument parsing errors.
+#[derive(Debug, Error)]
+pub enum LivelyError {
+    #[error(
+        "cannot connect to a Wayland compositor ({0}); lively-rs only runs in a Wayland session"
+    )]
+    WaylandConnect(String),
+    #[error("the compositor doesn't support {0}, which lively-rs needs (wlroots-based compositors such as Sway and Hyprland do)")]
+    MissingProtocol(&'static str),
+    #[error("no usable graphics adapter: {0}")]
+    NoAdapter(String),
+    #[error("failed to set up the graphics device: {0}")]
+    DeviceRequest(String),
+    #[error("{0}")]
+    Config(String),
+    #[error("{0}")]
+    Wayland(String),
+    #[error("event loop failed: {0}")]
+    EventLoop(String),
+}
+
+impl LivelyError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            LivelyError::WaylandConnect(_) => 3,
+            LivelyError::MissingProtocol(_) => 4,
+            LivelyError::NoAdapter(_) => 5,
+            LivelyError::DeviceRequest(_) => 6,
+            LivelyError::Config(_) => 7,
+            LivelyError::Wayland(_) => 8,
+            LivelyError::EventLoop(_) => 1,
+        }
+    }
+}
@@ -0,0 +1,335 @@
+// Image textures shaders can sample as iChannel0 to iChannel3, bound in group 1.
+use crate::config::{ChannelConfig, ChannelFilter, ChannelWrap};
+use image::codecs::{gif::GifDecoder, png::PngDecoder};
+use image::imageops::{self, FilterType};
+use image::{AnimationDecoder, ImageFormat};
+use std::fs::File;
+use std::io::BufReader;
+use std::num::NonZeroU32;
+use std::path::Path;
+use std::time::Duration;
+
+pub const CHANNEL_COUNT: usize = 4;
+
+/// Frame delays below this are an old way of asking for "as fast as possible", which browsers
+/// answer with [`DEFAULT_FRAME_DELAY`]; so do we.
+const MIN_FRAME_DELAY: Duration = Duration::from_millis(20);
+const DEFAULT_FRAME_DELAY: Duration = Duration::from_millis(100);
+
+/// An image decoded to RGBA with its mip chain, kept so it can be uploaded again whenever the
+/// pipeline is rebuilt.
+pub struct ChannelImage {
+    /// Mip levels as (width, height, RGBA pixels), largest first.
+    levels: Vec<(u32, u32, Vec<u8>)>,
+    wrap: ChannelWrap,
+    filter: ChannelFilter,
+}
+
+impl ChannelImage {
+    pub fn load(config: &ChannelConfig) -> Result<Self, String> {
+        let image = image::open(&config.path)
+            .map_err(|err| format!("Failed to load {}: {}", config.path.display(), err))?;
+        let image = if config.vflip { image.flipv() } else { image };
+        // Mipmaps are made up front so minified images don't shimmer.
+        let mut level = image.into_rgba8();
+        let mut levels = Vec::new();
+        loop {
+            let (width, height) = level.dimensions();
+            let next = (width > 1 || height > 1).then(|| {
+                imageops::resize(
+                    &level,
+                    (width / 2).max(1),
+                    (height / 2).max(1),
+                    FilterType::Triangle,
+                )
+            });
+            levels.push((width, height, level.into_raw()));
+            match next {
+                Some(next) => level = next,
+                None => break,
+            }
+        }
+        log::info!(
+            "Loaded {} ({}x{}, {} mip levels)",
+            config.path.display(),
+            levels[0].0,
+            levels[0].1,
+            levels.len()
+        );
+        Ok(Self {
+            levels,
+            wrap: config.wrap,
+            filter: config.filter,
+        })
+    }
+
+    /// A black image, for textures whose contents are uploaded later.
+    pub fn blank(width: u32, height: u32, filter: ChannelFilter) -> Self {
+        Self {
+            levels: vec![(
+                width,
+                height,
+                [0, 0, 0, 255].repeat((width * height) as usize),
+            )],
+            wrap: ChannelWrap::Clamp,
+            filter,
+        }
+    }
+
+    /// The 1x1 black texture bound to channels without an image.
+    fn black() -> Self {
+        Self::blank(1, 1, ChannelFilter::Nearest)
+    }
+}
+
+/// The frames of an animated GIF or APNG. The decoder composites each frame onto the previous
+/// ones, so disposal methods are already applied.
+pub struct Animation {
+    width: u32,
+    height: u32,
+    /// RGBA pixels of each frame and how long it is shown.
+    frames: Vec<(Vec<u8>, Duration)>,
+    current: usize,
+}
+
+impl Animation {
+    /// Decodes all frames of `path` if it is an animated GIF or APNG, None for other images.
+    pub fn load(path: &Path) -> Result<Option<Self>, String> {
+        let error = |err: image::ImageError| format!("Failed to load {}: {}", path.display(), err);
+        let open = || {
+            File::open(path)
+                .map(BufReader::new)
+                .map_err(|err| format!("Failed to load {}: {}", path.display(), err))
+        };
+        let frames = match ImageFormat::from_path(path) {
+            Ok(ImageFormat::Gif) => GifDecoder::new(open()?).map_err(error)?.into_frames(),
+            Ok(ImageFormat::Png) => {
+                let decoder = PngDecoder::new(open()?).map_err(error)?;
+                if !decoder.is_apng() {
+                    return Ok(None);
+                }
+                decoder.apng().into_frames()
+            }
+            _ => return Ok(None),
+        };
+        let frames = frames.collect_frames().map_err(error)?;
+        if frames.len() < 2 {
+            return Ok(None);
+        }
+        let (width, height) = frames[0].buffer().dimensions();
+        let frames: Vec<(Vec<u8>, Duration)> = frames
+            .into_iter()
+            .map(|frame| {
+                let delay = Duration::from(frame.delay());
+                let delay = if delay < MIN_FRAME_DELAY {
+                    DEFAULT_FRAME_DELAY
+                } else {
+                    delay
+                };
+                (frame.into_buffer().into_raw(), delay)
+            })
+            .collect();
+        log::info!(
+            "Loaded {} ({}x{}, {} frames)",
+            path.display(),
+            width,
+            height,
+            frames.len()
+        );
+        Ok(Some(Self {
+            width,
+            height,
+            frames,
+            current: 0,
+        }))
+    }
+
+    /// The first frame as a channel image. It has no mipmaps, so the other frames can replace it.
+    pub fn first_frame(&self, wrap: ChannelWrap) -> ChannelImage {
+        ChannelImage {
+            levels: vec![(self.width, self.height, self.frames[0].0.clone())],
+            wrap,
+            filter: ChannelFilter::Linear,
+        }
+    }
+
+    /// How long the current frame is shown.
+    pub fn delay(&self) -> Duration {
+        self.frames[self.current].1
+    }
+
+    /// Index of the current frame.
+    pub fn frame(&self) -> usize {
+        self.current
+    }
+
+    /// Moves on to the next frame, looping forever.
+    pub fn advance(&mut self) {
+        self.current = (self.current + 1) % self.frames.len();
+    }
+
+    /// Uploads the current frame to the texture made from [`Animation::first_frame`].
+    pub fn upload(&self, queue: &wgpu::Queue, texture: &wgpu::Texture) {
+        queue.write_texture(
+            texture.as_image_copy(),
+            &self.frames[self.current].0,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: NonZeroU32::new(4 * self.width),
+                rows_per_image: None,
+            },
+            wgpu::Extent3d {
+                width: self.width,
+                height: self.height,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+}
+
+/// Bind group 1, with channel `n` as a texture at binding `2n` and its sampler at `2n + 1`:
+///
+/// ```wgsl
+/// @group(1) @binding(0) var iChannel0: texture_2d<f32>;
+/// @group(1) @binding(1) var iChannel0_sampler: sampler;
+/// ```
+pub struct ChannelBindings {
+    pub layout: wgpu::BindGroupLayout,
+    pub bind_group: wgpu::BindGroup,
+    pub textures: Vec<wgpu::Texture>,
+}
+
+impl ChannelBindings {
+    /// Uploads the channel images; missing ones are bound as black.
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        images: &[Option<ChannelImage>],
+    ) -> Self {
+        let entries: Vec<wgpu::BindGroupLayoutEntry> = (0..CHANNEL_COUNT as u32)
+            .flat_map(|channel| {
+                [
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2 * channel,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2 * channel + 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                ]
+            })
+            .collect();
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("channels"),
+            entries: &entries,
+        });
+        let black = ChannelImage::black();
+        let (textures, resources): (Vec<_>, Vec<_>) = (0..CHANNEL_COUNT)
+            .map(|channel| {
+                let image = images
+                    .get(channel)
+                    .and_then(Option::as_ref)
+                    .unwrap_or(&black);
+                upload(device, queue, image)
+            })
+            .unzip();
+        let entries: Vec<wgpu::BindGroupEntry> = resources
+            .iter()
+            .zip(0..)
+            .flat_map(|((view, sampler), channel)| {
+                [
+                    wgpu::BindGroupEntry {
+                        binding: 2 * channel,
+                        resource: wgpu::BindingResource::TextureView(view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2 * channel + 1,
+                        resource: wgpu::BindingResource::Sampler(sampler),
+                    },
+                ]
+            })
+            .collect();
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("channels"),
+            layout: &layout,
+            entries: &entries,
+        });
+        Self {
+            layout,
+            bind_group,
+            textures,
+        }
+    }
+}
+
+fn upload(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    image: &ChannelImage,
+) -> (wgpu::Texture, (wgpu::TextureView, wgpu::Sampler)) {
+    // Images too large for the device start at the first mip level that fits.
+    let max_size = device.limits().max_texture_dimension_2d;
+    let levels: Vec<_> = image
+        .levels
+        .iter()
+        .skip_while(|(width, height, _)| *width > max_size || *height > max_size)
+        .collect();
+    let (width, height, _) = *levels[0];
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("channel"),
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: levels.len() as u32,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba8Unorm,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+    for (mip_level, (width, height, pixels)) in levels.into_iter().enumerate() {
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &texture,
+                mip_level: mip_level as u32,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            pixels,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: NonZeroU32::new(4 * width),
+                rows_per_image: None,
+            },
+            wgpu::Extent3d {
+                width: *width,
+                height: *height,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+    let address_mode = image.wrap.into();
+    let filter = image.filter.into();
+    let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+        label: Some("channel"),
+        address_mode_u: address_mode,
+        address_mode_v: address_mode,
+        mag_filter: filter,
+        min_filter: filter,
+        mipmap_filter: filter,
+        ..Default::default()
+    });
+    let view = texture.create_view(&Default::default());
+    (texture, (view, sampler))
+}
@@ -1,6 +1,17 @@
 // taken from https://github.com/gfx-rs/wgpu/blob/trunk/examples/common/src/framework.rs
+use super::renderer::{self, Renderer, ShaderRenderer};
+use crate::config::{BatteryPolicy, Config};
+use crate::error::LivelyError;
+use crate::power::{self, POWER_POLL_INTERVAL};
+use calloop::{
+    signals::{Signal, Signals},
+    timer::{TimeoutAction, Timer},
+    EventLoop, LoopHandle, RegistrationToken,
+};
+use input::event::keyboard::{KeyState, KeyboardEvent, KeyboardEventTrait};
 use input::event::pointer::PointerEvent as LibinputPointerEvent;
 use input::{Libinput, LibinputInterface};
+use nix::errno::Errno;
 use nix::poll::{poll, PollFd, PollFlags};
 use raw_window_handle::{
     HasRawDisplayHandle, HasRawWindowHandle, RawDisplayHandle, RawWindowHandle,
@@ -10,54 +21,232 @@ use smithay_client_toolkit::{
     compositor::CompositorState,
     delegate_compositor, delegate_keyboard, delegate_layer, delegate_output, delegate_pointer,
     delegate_registry, delegate_seat,
-    output::OutputState,
-    registry::RegistryState,
+    output::{OutputHandler, OutputState},
+    registry::{ProvidesRegistryState, RegistryState},
+    registry_handlers,
     seat::SeatState,
     shell::{
-        wlr_layer::{Anchor, KeyboardInteractivity, Layer, LayerShell, LayerSurface},
+        wlr_layer::{LayerShell, LayerSurface},
         WaylandSurface,
     },
 };
+use std::collections::HashMap;
 use std::fs::{File, OpenOptions};
 use std::os::fd::AsRawFd;
 use std::os::unix::{fs::OpenOptionsExt, io::OwnedFd};
-use std::path::Path;
-use std::sync::Mutex;
-use std::thread;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 use wayland_client::{
+    backend::{ObjectId, WaylandError},
     globals::registry_queue_init,
-    protocol::{wl_keyboard, wl_pointer, wl_surface},
-    Connection, Proxy,
+    protocol::{wl_keyboard, wl_output, wl_pointer, wl_surface},
+    Connection, DispatchError, Proxy, QueueHandle, WaylandSource,
+};
+use wayland_protocols::wp::fractional_scale::v1::client::{
+    wp_fractional_scale_manager_v1::WpFractionalScaleManagerV1,
+    wp_fractional_scale_v1::WpFractionalScaleV1,
+};
+use wayland_protocols::wp::viewporter::client::{
+    wp_viewport::WpViewport, wp_viewporter::WpViewporter,
+};
+use wayland_protocols_wlr::foreign_toplevel::v1::client::{
+    zwlr_foreign_toplevel_handle_v1::ZwlrForeignToplevelHandleV1,
+    zwlr_foreign_toplevel_manager_v1::ZwlrForeignToplevelManagerV1,
+};
+use wayland_protocols_wlr::output_power_management::v1::client::{
+    zwlr_output_power_manager_v1::ZwlrOutputPowerManagerV1, zwlr_output_power_v1::ZwlrOutputPowerV1,
 };
 
 #[allow(dead_code)]
-pub enum ShaderStage {
+pub(crate) enum ShaderStage {
     Vertex,
     Fragment,
     Compute,
 }
-pub static POINTER_POS: Mutex<(f64, f64)> = Mutex::new((0.0, 0.0));
-
-pub struct Wallpaper {
-    pub registry_state: RegistryState,
-    pub seat_state: SeatState,
-    pub output_state: OutputState,
-    pub exit: bool,
-    pub first_configure: bool,
-    pub width: u32,
-    pub height: u32,
-    pub adapter: wgpu::Adapter,
-    pub queue: wgpu::Queue,
-    pub device: wgpu::Device,
-    pub surface: wgpu::Surface,
-    pub wl_surface: wl_surface::WlSurface,
-
-    pub shift: Option<u32>,
-    pub layer: LayerSurface,
-    pub keyboard: Option<wl_keyboard::WlKeyboard>,
-    pub keyboard_focus: bool,
-    pub pointer: Option<wl_pointer::WlPointer>,
+/// Pointer position in global logical (compositor layout) coordinates.
+pub(crate) static POINTER_POS: Mutex<(f64, f64)> = Mutex::new((0.0, 0.0));
+/// Scale factor of the output the wallpaper is on. libinput reports motion in roughly physical
+/// pixels, which is divided by this to move in logical coordinates.
+pub(crate) static POINTER_SCALE: AtomicI32 = AtomicI32::new(1);
+/// Set while every output the wallpaper is on is powered off, so nobody sees the mouse effect.
+pub(crate) static MOUSE_SUSPENDED: AtomicBool = AtomicBool::new(false);
+/// Instant `main()` started at, used as the origin of the startup timeline.
+pub(crate) static STARTUP: OnceLock<Instant> = OnceLock::new();
+
+/// Logs how many milliseconds passed between `main()` and `event`.
+pub(crate) fn log_startup_event(event: &str) {
+    let start = STARTUP.get_or_init(Instant::now);
+    log::info!("startup: {} at {}ms", event, start.elapsed().as_millis());
+}
+
+pub(crate) struct Wallpaper<R: Renderer = ShaderRenderer> {
+    pub(crate) registry_state: RegistryState,
+    pub(crate) seat_state: SeatState,
+    pub(crate) output_state: OutputState,
+    pub(crate) exit: bool,
+    /// Why the wallpaper is exiting, if it's because something failed.
+    pub(crate) fatal: Option<LivelyError>,
+    pub(crate) first_present: bool,
+    /// Origin of the `time` uniform.
+    pub(crate) started: Instant,
+    pub(crate) config: Config,
+    pub(crate) loop_handle: LoopHandle<'static, Wallpaper<R>>,
+    pub(crate) conn: Connection,
+    pub(crate) compositor: CompositorState,
+    pub(crate) layer_shell: LayerShell,
+    pub(crate) instance: wgpu::Instance,
+    /// Index into [`BACKEND_CHAIN`] of the backend `instance` was created for.
+    pub(crate) backend_index: usize,
+    pub(crate) backend_failures: Vec<String>,
+    pub(crate) failed_presents: u32,
+    /// Device resets since the last successful present.
+    pub(crate) device_resets: u32,
+    pub(crate) requirements: GpuRequirements,
+    /// Created lazily once the first layer surface has been configured.
+    pub(crate) gpu: Option<Gpu>,
+    /// The renderer of each layer surface and the swapchain format it was made for, dropped
+    /// with the device.
+    pub(crate) renderers: HashMap<ObjectId, (wgpu::TextureFormat, R)>,
+
+    pub(crate) shift: Option<u32>,
+    pub(crate) mouse_down: bool,
+    /// Shadertoy-style `iMouse`, see [`super::pipeline::Uniforms::imouse`].
+    pub(crate) imouse: [f32; 4],
+    /// One layer surface per output the wallpaper is shown on.
+    pub(crate) targets: Vec<OutputTarget>,
+    /// The output the pointer was last seen on, whose scale libinput motion is divided by.
+    pub(crate) pointer_output: Option<wl_output::WlOutput>,
+    /// `None` when the compositor doesn't implement wlr-output-power-management.
+    pub(crate) power_manager: Option<ZwlrOutputPowerManagerV1>,
+    /// Both are needed for fractional scaling, which falls back to integer buffer scales
+    /// when either is missing.
+    pub(crate) fractional_scale_manager: Option<WpFractionalScaleManagerV1>,
+    pub(crate) viewporter: Option<WpViewporter>,
+    /// Used to find fullscreen windows covering the wallpaper. `None` when pausing on
+    /// fullscreen is disabled or the compositor doesn't implement wlr-foreign-toplevel.
+    pub(crate) toplevel_manager: Option<ZwlrForeignToplevelManagerV1>,
+    pub(crate) toplevels: Vec<Toplevel>,
+    /// Whether the system runs on battery.
+    pub(crate) on_battery: bool,
+    /// Paused through the control socket.
+    pub(crate) user_paused: bool,
+    pub(crate) keyboard: Option<wl_keyboard::WlKeyboard>,
+    pub(crate) keyboard_focus: bool,
+    pub(crate) pointer: Option<wl_pointer::WlPointer>,
+}
+
+/// The layer surface on one output and everything rendering to it.
+pub(crate) struct OutputTarget {
+    pub(crate) output: wl_output::WlOutput,
+    // The wgpu surface must be dropped before the layer surface it was created from.
+    pub(crate) surface: Option<wgpu::Surface>,
+    /// The configuration `surface` was last configured with.
+    pub(crate) surface_config: Option<wgpu::SurfaceConfiguration>,
+    pub(crate) layer: LayerSurface,
+    /// Whether no usable configure has arrived for this layer yet.
+    pub(crate) first_configure: bool,
+    pub(crate) frame_requested: Option<Instant>,
+    /// When the next frame is due under the frame-rate cap. Each deadline is one interval after
+    /// the previous one rather than after the last draw, so the cap doesn't drift.
+    pub(crate) next_frame: Option<Instant>,
+    /// Pending timer that draws the next frame once the frame-rate cap allows it.
+    pub(crate) frame_timer: Option<RegistrationToken>,
+    /// Last size accepted from a configure, the only one we present at. Degenerate configures
+    /// don't replace it, so rendering carries on at the old size through them.
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    /// Scale of the output in 120ths, the unit wp-fractional-scale uses. Without fractional
+    /// scaling this is a multiple of 120 matching the integer buffer scale.
+    pub(crate) scale_120: u32,
+    /// Set when fractional scaling is in use; the buffer scale then stays 1 and the viewport
+    /// maps the buffer back to the logical size.
+    pub(crate) fractional_scale: Option<WpFractionalScaleV1>,
+    pub(crate) viewport: Option<WpViewport>,
+    pub(crate) output_power: Option<ZwlrOutputPowerV1>,
+    /// Whether the output is powered on (DPMS).
+    pub(crate) output_powered: bool,
+    /// Whether a fullscreen window covers the whole output.
+    pub(crate) occluded: bool,
+}
+
+/// A window as announced by wlr-foreign-toplevel, with the state from its last `done`.
+pub(crate) struct Toplevel {
+    pub(crate) handle: ZwlrForeignToplevelHandleV1,
+    pub(crate) outputs: Vec<wl_output::WlOutput>,
+    pub(crate) fullscreen: bool,
+    pub(crate) activated: bool,
+    pub(crate) minimized: bool,
+}
+
+impl OutputTarget {
+    /// Converts a logical size to physical pixels, rounding halfway away from zero as
+    /// wp-fractional-scale asks.
+    pub(crate) fn to_physical(&self, (width, height): (u32, u32)) -> (u32, u32) {
+        (
+            (width * self.scale_120 + 60) / 120,
+            (height * self.scale_120 + 60) / 120,
+        )
+    }
+
+    /// Size of the swapchain in physical pixels.
+    pub(crate) fn buffer_size(&self) -> (u32, u32) {
+        self.to_physical((self.width, self.height))
+    }
+
+    /// Whether anything rendered to this layer could be seen.
+    pub(crate) fn visible(&self) -> bool {
+        self.output_powered && !self.occluded
+    }
+}
+
+impl Drop for OutputTarget {
+    fn drop(&mut self) {
+        if let Some(viewport) = self.viewport.take() {
+            viewport.destroy();
+        }
+        if let Some(fractional_scale) = self.fractional_scale.take() {
+            fractional_scale.destroy();
+        }
+        if let Some(power) = self.output_power.take() {
+            power.destroy();
+        }
+    }
+}
+
+pub(crate) struct Gpu {
+    pub(crate) adapter: wgpu::Adapter,
+    pub(crate) device: wgpu::Device,
+    pub(crate) queue: wgpu::Queue,
+    /// Set from wgpu's error handler when the device ran out of memory; everything created
+    /// from it has to be made again. wgpu has no device-lost callback yet, so a lost device
+    /// (GPU reset, some drivers across suspend) is instead noticed in `draw`, when the
+    /// swapchain is lost and configuring it again fails.
+    pub(crate) lost: Arc<AtomicBool>,
+}
+
+/// The device requirements of a [`WgpuConfig`], captured at startup so the device can be
+/// requested later, once there is a surface to be compatible with.
+pub(crate) struct GpuRequirements {
+    pub(crate) optional_features: wgpu::Features,
+    pub(crate) required_features: wgpu::Features,
+    pub(crate) required_downlevel_capabilities: wgpu::DownlevelCapabilities,
+    pub(crate) required_limits: wgpu::Limits,
+}
+
+impl GpuRequirements {
+    fn of<E: WgpuConfig>() -> Self {
+        Self {
+            optional_features: E::optional_features(),
+            required_features: E::required_features(),
+            required_downlevel_capabilities: E::required_downlevel_capabilities(),
+            required_limits: E::required_limits(),
+        }
+    }
 }
+
 pub trait WgpuConfig: 'static + Sized {
     fn optional_features() -> wgpu::Features {
         wgpu::Features::empty()
@@ -77,166 +266,943 @@ pub trait WgpuConfig: 'static + Sized {
     }
 }
 
-pub async fn setup<E: WgpuConfig>() {
-    env_logger::init();
-    // All Wayland apps start by connecting the compositor (server).
-    let conn = Connection::connect_to_env().unwrap();
+impl<R: Renderer> Wallpaper<R> {
+    /// Creates a layer surface on every known output the config allows that doesn't have one
+    /// yet. With no such output the wallpaper idles until `new_output` fires.
+    pub(crate) fn ensure_layers(&mut self, qh: &QueueHandle<Self>) {
+        let missing: Vec<_> = self
+            .output_state
+            .outputs()
+            .filter(|output| self.wants_output(output) && self.target_for_output(output).is_none())
+            .collect();
+        for output in missing {
+            self.create_layer(qh, output);
+        }
+        if !self.targets.is_empty() {
+            return;
+        }
+        if self.config.outputs.is_empty() {
+            log::info!("No outputs available, waiting for one to appear");
+        } else {
+            log::info!(
+                "None of the outputs {:?} are connected, waiting for one to appear",
+                self.config.outputs
+            );
+        }
+    }
 
-    // Enumerate the list of globals to get the protocols the server implements.
-    let (globals, mut event_queue) = registry_queue_init(&conn).unwrap();
-    let qh = event_queue.handle();
+    /// Whether the config allows putting the wallpaper on `output`, matching either its
+    /// connector name (DP-2) or its description (the monitor model).
+    fn wants_output(&self, output: &wl_output::WlOutput) -> bool {
+        if self.config.outputs.is_empty() {
+            return true;
+        }
+        let Some(info) = self.output_state.info(output) else {
+            return false;
+        };
+        [info.name, info.description]
+            .into_iter()
+            .flatten()
+            .any(|name| self.config.outputs.contains(&name))
+    }
 
-    // The compositor (not to be confused with the server which is commonly called the compositor) allows
-    // configuring surfaces to be presented.
-    let compositor = CompositorState::bind(&globals, &qh).expect("wl_compositor is not available");
-    let surface = compositor.create_surface(&qh);
-    // This app uses the wlr layer shell, which may not be available with every compositor.
-    let layer_shell = LayerShell::bind(&globals, &qh).expect("layer shell is not available");
-    // Initialize wgpu
-    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
-        backends: wgpu::Backends::all(),
-        ..Default::default()
-    });
-    log::info!("Initializing layer_shell");
-    // And then we create the layer shell.
-    let layer = layer_shell.create_layer_surface(
-        &qh,
-        surface,
-        Layer::Background,
-        Some("simple_layer"),
-        None,
-    );
-    // Create the raw window handle for the surface.
-    let handle = {
-        let mut handle = WaylandDisplayHandle::empty();
-        handle.display = conn.backend().display_ptr() as *mut _;
-        let display_handle = RawDisplayHandle::Wayland(handle);
-
-        let mut handle = WaylandWindowHandle::empty();
-        let wl_surface = layer.wl_surface();
-        handle.surface = wl_surface.id().as_ptr() as *mut _;
-        let window_handle = RawWindowHandle::Wayland(handle);
-
-        /// https://github.com/rust-windowing/raw-window-handle/issues/49
-        struct YesRawWindowHandleImplementingHasRawWindowHandleIsUnsound(
-            RawDisplayHandle,
-            RawWindowHandle,
+    pub(crate) fn create_layer(&mut self, qh: &QueueHandle<Self>, output: wl_output::WlOutput) {
+        log::info!("Initializing layer_shell");
+        let surface = self.compositor.create_surface(qh);
+        // And then we create the layer shell.
+        let layer = self.layer_shell.create_layer_surface(
+            qh,
+            surface,
+            self.config.layer.layer.into(),
+            Some("simple_layer"),
+            Some(&output),
         );
+        // Configure the layer surface, providing things like the anchor on screen, desired size and the keyboard
+        // interactivity
+        let options = &self.config.layer;
+        layer.set_anchor(options.anchor());
+        let [top, right, bottom, left] = options.margin;
+        layer.set_margin(top, right, bottom, left);
+        let [width, height] = options.size;
+        layer.set_size(width, height);
+        layer.set_keyboard_interactivity(options.keyboard_interactivity.into());
+        layer.set_exclusive_zone(options.exclusive_zone);
+        // Start out at the output's scale so the first frame is already sharp; the compositor
+        // reports the exact scale once the surface has entered the output.
+        let scale = self
+            .output_state
+            .info(&output)
+            .map_or(1, |info| info.scale_factor.max(1));
+        let (fractional_scale, viewport) = match (&self.fractional_scale_manager, &self.viewporter)
+        {
+            (Some(manager), Some(viewporter)) => (
+                Some(manager.get_fractional_scale(layer.wl_surface(), qh, ())),
+                Some(viewporter.get_viewport(layer.wl_surface(), qh, ())),
+            ),
+            _ => {
+                layer.wl_surface().set_buffer_scale(scale);
+                (None, None)
+            }
+        };
+        layer.commit();
+        log_startup_event("layer surface committed");
+        let output_power = self
+            .power_manager
+            .as_ref()
+            .map(|manager| manager.get_output_power(&output, qh, ()));
+        if self.pointer_output.is_none() {
+            self.pointer_output = Some(output.clone());
+            self.update_pointer_scale();
+        }
+        self.targets.push(OutputTarget {
+            output,
+            surface: None,
+            surface_config: None,
+            layer,
+            first_configure: true,
+            frame_requested: None,
+            next_frame: None,
+            frame_timer: None,
+            width: 0,
+            height: 0,
+            scale_120: scale as u32 * 120,
+            fractional_scale,
+            viewport,
+            output_power,
+            output_powered: true,
+            occluded: false,
+        });
+        self.update_mouse_suspension();
+    }
 
-        unsafe impl HasRawDisplayHandle for YesRawWindowHandleImplementingHasRawWindowHandleIsUnsound {
-            fn raw_display_handle(&self) -> RawDisplayHandle {
-                self.0
+    pub(crate) fn target_for_output(&self, output: &wl_output::WlOutput) -> Option<usize> {
+        self.targets
+            .iter()
+            .position(|target| target.output == *output)
+    }
+
+    pub(crate) fn target_for_surface(&self, surface: &wl_surface::WlSurface) -> Option<usize> {
+        self.targets
+            .iter()
+            .position(|target| target.layer.wl_surface() == surface)
+    }
+
+    /// Logical position and size of `output`.
+    pub(crate) fn output_geometry(
+        &self,
+        output: &wl_output::WlOutput,
+    ) -> Option<((f64, f64), (f64, f64))> {
+        let info = self.output_state.info(output)?;
+        let (x, y) = info.logical_position?;
+        let (width, height) = info.logical_size?;
+        Some(((x as f64, y as f64), (width as f64, height as f64)))
+    }
+
+    /// Bounding box of all outputs in logical coordinates, as (min, max) corners.
+    fn desktop_bounds(&self) -> Option<((f64, f64), (f64, f64))> {
+        self.output_state
+            .outputs()
+            .filter_map(|output| self.output_state.info(&output))
+            .filter_map(|info| Some((info.logical_position?, info.logical_size?)))
+            .map(|((x, y), (w, h))| ((x as f64, y as f64), ((x + w) as f64, (y + h) as f64)))
+            .reduce(|(min, max), (other_min, other_max)| {
+                (
+                    (min.0.min(other_min.0), min.1.min(other_min.1)),
+                    (max.0.max(other_max.0), max.1.max(other_max.1)),
+                )
+            })
+    }
+
+    /// Keeps the pointer scale in sync with the output the pointer is on.
+    pub(crate) fn update_pointer_scale(&self) {
+        let scale = self
+            .pointer_output
+            .as_ref()
+            .and_then(|output| self.output_state.info(output))
+            .map_or(1, |info| info.scale_factor.max(1));
+        POINTER_SCALE.store(scale, Ordering::Relaxed);
+    }
+
+    /// The pointer position relative to the output of `target`, in 0..1. libinput deltas are
+    /// accumulated without knowing the screen edges, so the global position is clamped to the
+    /// desktop here; a pointer on another output is clamped to the nearest edge of this one.
+    pub(crate) fn normalized_mouse(&self, target: &OutputTarget) -> [f32; 2] {
+        let mut pos = POINTER_POS.lock().unwrap();
+        if let Some((min, max)) = self.desktop_bounds() {
+            pos.0 = pos.0.clamp(min.0, max.0);
+            pos.1 = pos.1.clamp(min.1, max.1);
+        }
+        let ((x, y), (width, height)) = self
+            .output_geometry(&target.output)
+            .unwrap_or(((0.0, 0.0), (target.width as f64, target.height as f64)));
+        [
+            ((pos.0 - x) / width).clamp(0.0, 1.0) as f32,
+            ((pos.1 - y) / height).clamp(0.0, 1.0) as f32,
+        ]
+    }
+
+    /// Drops a layer surface and everything rendering to it. The device is kept around.
+    pub(crate) fn destroy_layer(&mut self, index: usize) {
+        let target = self.targets.remove(index);
+        self.renderers.remove(&target.layer.wl_surface().id());
+        if let Some(timer) = target.frame_timer {
+            self.loop_handle.remove(timer);
+        }
+        self.update_mouse_suspension();
+        if self.pointer_output.as_ref() == Some(&target.output) {
+            self.pointer_output = self.targets.first().map(|target| target.output.clone());
+            self.update_pointer_scale();
+        }
+    }
+
+    /// Minimum time between frames, `None` when uncapped.
+    pub(crate) fn frame_interval(&self) -> Option<Duration> {
+        let mut fps = self.config.fps;
+        if self.on_battery && self.config.battery.policy == BatteryPolicy::Throttle {
+            fps = match fps {
+                0 => self.config.battery.fps,
+                fps => fps.min(self.config.battery.fps),
+            };
+        }
+        (fps > 0).then(|| Duration::from_secs(1) / fps)
+    }
+
+    /// Whether rendering is paused on all outputs.
+    pub(crate) fn paused(&self) -> bool {
+        self.user_paused || self.on_battery && self.config.battery.policy == BatteryPolicy::Pause
+    }
+
+    /// Pauses or resumes rendering on all outputs, for the control socket and SIGUSR1.
+    pub(crate) fn set_paused(&mut self, qh: &QueueHandle<Self>, paused: bool) {
+        log::info!("{} rendering", if paused { "Pausing" } else { "Resuming" });
+        self.user_paused = paused;
+        self.resume_idle(qh);
+    }
+
+    /// Switches to the shader at `path`, for the control socket and SIGUSR2. Only the
+    /// [`ShaderRenderer`] draws shaders; with any other renderer this fails.
+    pub(crate) fn set_shader(
+        &mut self,
+        qh: &QueueHandle<Self>,
+        path: PathBuf,
+    ) -> Result<(), String> {
+        renderer::switch_shader(&path, self.config.shadertoy)?;
+        self.config.shader = Some(path);
+        // Leaves the image mode, if it was on.
+        self.config.image = None;
+        self.resume_idle(qh);
+        Ok(())
+    }
+
+    /// Restarts the frame loops of layers that stopped rendering, e.g. after resuming.
+    pub(crate) fn resume_idle(&mut self, qh: &QueueHandle<Self>) {
+        for index in 0..self.targets.len() {
+            if self.idle(index) {
+                self.draw(qh, index);
             }
         }
+    }
+
+    /// Shows the next frame of an animated image on the layers that are rendering. Returns how
+    /// long until the frame after, None once there is no animation anymore.
+    pub(crate) fn next_animation_frame(&mut self, qh: &QueueHandle<Self>) -> Option<Duration> {
+        let rendering: Vec<usize> = (0..self.targets.len())
+            .filter(|&index| self.should_render(index))
+            .collect();
+        // Nobody sees the animation; it carries on from the same frame later.
+        if rendering.is_empty() {
+            return renderer::with_animation(|animation| animation.delay());
+        }
+        // The renderers upload the new frame when they draw it.
+        let delay = renderer::with_animation(|animation| {
+            animation.advance();
+            animation.delay()
+        })?;
+        for index in rendering {
+            if self.idle(index) {
+                self.draw(qh, index);
+            }
+        }
+        Some(delay)
+    }
+
+    /// Suspends the mouse tracker while all of our outputs are powered off.
+    pub(crate) fn update_mouse_suspension(&self) {
+        let suspended =
+            !self.targets.is_empty() && self.targets.iter().all(|target| !target.output_powered);
+        MOUSE_SUSPENDED.store(suspended, Ordering::Relaxed);
+    }
+
+    /// Whether the layer should keep rendering frames.
+    pub(crate) fn should_render(&self, index: usize) -> bool {
+        !self.paused() && self.targets[index].visible()
+    }
+
+    /// Applies the battery policy after the power source changed.
+    pub(crate) fn set_on_battery(&mut self, qh: &QueueHandle<Self>, on_battery: bool) {
+        if on_battery == self.on_battery {
+            return;
+        }
+        log::info!(
+            "Running on {}, battery policy {:?}",
+            if on_battery { "battery" } else { "AC power" },
+            self.config.battery.policy
+        );
+        self.on_battery = on_battery;
+        self.resume_idle(qh);
+    }
+
+    /// Draws the next frame of a layer in response to a frame callback, or arms a timer to draw
+    /// it later if that would exceed the frame-rate cap. Waiting for frame callbacks in the
+    /// first place keeps hidden outputs from rendering either way.
+    pub(crate) fn frame_due(&mut self, qh: &QueueHandle<Self>, index: usize) {
+        let target = &self.targets[index];
+        let due = self
+            .frame_interval()
+            .and(target.next_frame)
+            .filter(|due| *due > Instant::now());
+        let Some(due) = due else {
+            self.draw(qh, index);
+            return;
+        };
+        let surface = target.layer.wl_surface().clone();
+        let timer_qh = qh.clone();
+        let timer = self
+            .loop_handle
+            .insert_source(Timer::from_deadline(due), move |_, _, w| {
+                if let Some(index) = w.target_for_surface(&surface) {
+                    w.targets[index].frame_timer = None;
+                    if w.should_render(index) {
+                        w.draw(&timer_qh, index);
+                    }
+                }
+                TimeoutAction::Drop
+            });
+        match timer {
+            Ok(timer) => self.targets[index].frame_timer = Some(timer),
+            // Drawing early beats stopping the frame loop.
+            Err(err) => {
+                log::error!("Failed to insert frame timer, drawing now: {}", err.error);
+                self.draw(qh, index);
+            }
+        }
+    }
+
+    /// Whether `surface` is the surface of one of our layers.
+    pub(crate) fn is_layer_surface(&self, surface: &wl_surface::WlSurface) -> bool {
+        self.target_for_surface(surface).is_some()
+    }
+
+    /// Creates the wgpu surface for a layer surface and, the first time around, picks an
+    /// adapter compatible with it and requests the device.
+    pub(crate) fn init_gpu(&mut self, index: usize) -> Result<(), LivelyError> {
+        let target = &mut self.targets[index];
+        if target.surface.is_none() {
+            let handle = raw_handle(&self.conn, target.layer.wl_surface());
+            // A layer surface is created from a surface.
+            let surface = unsafe { self.instance.create_surface(&handle) }.map_err(|err| {
+                LivelyError::DeviceRequest(format!("failed to create surface: {err}"))
+            })?;
+            target.surface = Some(surface);
+        }
+        if self.gpu.is_none() {
+            self.gpu = Some(request_gpu(
+                &self.instance,
+                self.targets[index].surface.as_ref(),
+                &self.requirements,
+            )?);
+            log_startup_event("device ready");
+        }
+        Ok(())
+    }
+
+    /// Brings up the device and configures the surfaces of all configured layers, walking down
+    /// [`BACKEND_CHAIN`] until a backend works or there are none left.
+    pub(crate) fn setup_gpu(&mut self) {
+        'retry: loop {
+            for index in 0..self.targets.len() {
+                // Layers without a usable size yet are set up once they get one.
+                if self.targets[index].first_configure {
+                    continue;
+                }
+                let result = self.init_gpu(index).and_then(|()| {
+                    self.configure_surface(index)
+                        .map_err(LivelyError::DeviceRequest)
+                });
+                if let Err(reason) = result {
+                    if !self.fall_back(reason) {
+                        return;
+                    }
+                    continue 'retry;
+                }
+            }
+            return;
+        }
+    }
+
+    /// Tears down everything wgpu created for the current backend and switches to the next one
+    /// in the chain, keeping the layer surfaces. Returns false once all backends have failed.
+    pub(crate) fn fall_back(&mut self, reason: LivelyError) -> bool {
+        let backend = BACKEND_CHAIN[self.backend_index];
+        log::warn!("{:?} backend failed: {}", backend, reason);
+        self.backend_failures.push(format!("{backend:?}: {reason}"));
+        self.renderers.clear();
+        for target in &mut self.targets {
+            target.surface_config = None;
+            target.surface = None;
+        }
+        self.gpu = None;
+        self.failed_presents = 0;
+        self.backend_index += 1;
+        if self.backend_index >= BACKEND_CHAIN.len() {
+            log::error!(
+                "No working graphics backend: {}",
+                self.backend_failures.join("; ")
+            );
+            self.fatal = Some(reason);
+            self.exit = true;
+            return false;
+        }
+        log::info!("Retrying with {:?}", BACKEND_CHAIN[self.backend_index]);
+        self.instance = create_instance(self.backend_index);
+        true
+    }
 
-        unsafe impl HasRawWindowHandle for YesRawWindowHandleImplementingHasRawWindowHandleIsUnsound {
-            fn raw_window_handle(&self) -> RawWindowHandle {
-                self.1
+    /// Replaces a lost device with a new one on the same backend, recreating the surfaces and
+    /// pipeline. A device that keeps getting lost before anything is presented counts as a
+    /// failed backend.
+    pub(crate) fn reset_gpu(&mut self) {
+        self.device_resets += 1;
+        if self.device_resets > MAX_DEVICE_RESETS {
+            self.device_resets = 0;
+            let reason = LivelyError::DeviceRequest("the device keeps getting lost".to_string());
+            if !self.fall_back(reason) {
+                return;
             }
+        } else {
+            log::warn!("Graphics device lost, reinitializing");
+            self.renderers.clear();
+            for target in &mut self.targets {
+                target.surface_config = None;
+                target.surface = None;
+            }
+            self.gpu = None;
+        }
+        self.setup_gpu();
+    }
+
+    /// Called after the first successful present, remembers the backend that worked.
+    pub(crate) fn backend_works(&self) {
+        let backend = BACKEND_CHAIN[self.backend_index];
+        if !self.backend_failures.is_empty() {
+            log::info!(
+                "Using {:?} after trying: {}",
+                backend,
+                self.backend_failures.join("; ")
+            );
         }
+        save_backend_index(self.backend_index);
+    }
+}
+
+/// Device losses in a row, without a present in between, before the backend is given up on.
+const MAX_DEVICE_RESETS: u32 = 3;
 
-        YesRawWindowHandleImplementingHasRawWindowHandleIsUnsound(display_handle, window_handle)
+/// Backends tried in order when the previous one can't configure the surface or present.
+pub(crate) const BACKEND_CHAIN: [wgpu::Backends; 2] = [wgpu::Backends::VULKAN, wgpu::Backends::GL];
+
+fn create_instance(backend_index: usize) -> wgpu::Instance {
+    wgpu::Instance::new(wgpu::InstanceDescriptor {
+        backends: BACKEND_CHAIN[backend_index],
+        ..Default::default()
+    })
+}
+
+fn backend_state_path() -> Option<PathBuf> {
+    let state_dir = std::env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".local/state")))?;
+    Some(state_dir.join("lively-rs").join("backend"))
+}
+
+/// The backend that worked last time, so later launches don't retry a broken one first.
+fn load_backend_index() -> usize {
+    backend_state_path()
+        .and_then(|path| std::fs::read_to_string(path).ok())
+        .and_then(|name| {
+            BACKEND_CHAIN
+                .iter()
+                .position(|backend| format!("{backend:?}") == name.trim())
+        })
+        .unwrap_or(0)
+}
+
+fn save_backend_index(backend_index: usize) {
+    let Some(path) = backend_state_path() else {
+        return;
     };
+    let result = std::fs::create_dir_all(path.parent().unwrap())
+        .and_then(|()| std::fs::write(&path, format!("{:?}", BACKEND_CHAIN[backend_index])));
+    if let Err(err) = result {
+        log::warn!(
+            "Failed to record working backend in {}: {}",
+            path.display(),
+            err
+        );
+    }
+}
+
+// Create the raw window handle for the surface.
+fn raw_handle(
+    conn: &Connection,
+    wl_surface: &wl_surface::WlSurface,
+) -> impl HasRawDisplayHandle + HasRawWindowHandle {
+    let mut handle = WaylandDisplayHandle::empty();
+    handle.display = conn.backend().display_ptr() as *mut _;
+    let display_handle = RawDisplayHandle::Wayland(handle);
+
+    let mut handle = WaylandWindowHandle::empty();
+    handle.surface = wl_surface.id().as_ptr() as *mut _;
+    let window_handle = RawWindowHandle::Wayland(handle);
+
+    /// https://github.com/rust-windowing/raw-window-handle/issues/49
+    struct YesRawWindowHandleImplementingHasRawWindowHandleIsUnsound(
+        RawDisplayHandle,
+        RawWindowHandle,
+    );
+
+    unsafe impl HasRawDisplayHandle for YesRawWindowHandleImplementingHasRawWindowHandleIsUnsound {
+        fn raw_display_handle(&self) -> RawDisplayHandle {
+            self.0
+        }
+    }
 
-    // A layer surface is created from a surface.
-    let surface = unsafe { instance.create_surface(&handle).unwrap() };
+    unsafe impl HasRawWindowHandle for YesRawWindowHandleImplementingHasRawWindowHandleIsUnsound {
+        fn raw_window_handle(&self) -> RawWindowHandle {
+            self.1
+        }
+    }
 
+    YesRawWindowHandleImplementingHasRawWindowHandleIsUnsound(display_handle, window_handle)
+}
+
+fn request_gpu(
+    instance: &wgpu::Instance,
+    surface: Option<&wgpu::Surface>,
+    requirements: &GpuRequirements,
+) -> Result<Gpu, LivelyError> {
     // Pick a supported adapter
     let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
-        compatible_surface: Some(&surface),
+        compatible_surface: surface,
         ..Default::default()
     }))
-    .expect("Failed to find suitable adapter");
-    let (_device, _queue) = pollster::block_on(adapter.request_device(&Default::default(), None))
-        .expect("Failed to request device");
-    // Configure the layer surface, providing things like the anchor on screen, desired size and the keyboard
-    // interactivity
-    layer.set_anchor(Anchor::TOP | Anchor::BOTTOM | Anchor::RIGHT | Anchor::LEFT);
-    layer.set_keyboard_interactivity(KeyboardInteractivity::OnDemand);
-    layer.set_exclusive_zone(-1);
-    layer.commit();
-    let wl_surface = layer.wl_surface().clone();
+    .ok_or_else(|| LivelyError::NoAdapter("none can present to the surface".to_string()))?;
 
     let adapter_info = adapter.get_info();
     println!("Using {} ({:?})", adapter_info.name, adapter_info.backend);
 
-    let optional_features = E::optional_features();
-    let required_features = E::required_features();
+    let optional_features = requirements.optional_features;
+    let required_features = requirements.required_features;
     let adapter_features = adapter.features();
-    assert!(
-        adapter_features.contains(required_features),
-        "Adapter does not support required features for this example: {:?}",
-        required_features - adapter_features
-    );
+    if !adapter_features.contains(required_features) {
+        return Err(LivelyError::NoAdapter(format!(
+            "{} does not support the required features {:?}",
+            adapter_info.name,
+            required_features - adapter_features
+        )));
+    }
 
-    let required_downlevel_capabilities = E::required_downlevel_capabilities();
+    let required_downlevel_capabilities = &requirements.required_downlevel_capabilities;
     let downlevel_capabilities = adapter.get_downlevel_capabilities();
-    assert!(
-        downlevel_capabilities.shader_model >= required_downlevel_capabilities.shader_model,
-        "Adapter does not support the minimum shader model required to run this example: {:?}",
-        required_downlevel_capabilities.shader_model
-    );
-    assert!(
-        downlevel_capabilities
-            .flags
-            .contains(required_downlevel_capabilities.flags),
-        "Adapter does not support the downlevel capabilities required to run this example: {:?}",
-        required_downlevel_capabilities.flags - downlevel_capabilities.flags
-    );
+    if downlevel_capabilities.shader_model < required_downlevel_capabilities.shader_model {
+        return Err(LivelyError::NoAdapter(format!(
+            "{} does not support shader model {:?}",
+            adapter_info.name, required_downlevel_capabilities.shader_model
+        )));
+    }
+    if !downlevel_capabilities
+        .flags
+        .contains(required_downlevel_capabilities.flags)
+    {
+        return Err(LivelyError::NoAdapter(format!(
+            "{} does not support the downlevel capabilities {:?}",
+            adapter_info.name,
+            required_downlevel_capabilities.flags - downlevel_capabilities.flags
+        )));
+    }
 
     // Make sure we use the texture resolution limits from the adapter, so we can support images the size of the surface.
-    let needed_limits = E::required_limits().using_resolution(adapter.limits());
+    let needed_limits = requirements
+        .required_limits
+        .clone()
+        .using_resolution(adapter.limits());
 
     let trace_dir = std::env::var("WGPU_TRACE");
-    let (device, queue) = adapter
-        .request_device(
-            &wgpu::DeviceDescriptor {
-                label: None,
-                features: (optional_features & adapter_features) | required_features,
-                limits: needed_limits,
-            },
-            trace_dir.ok().as_ref().map(std::path::Path::new),
-        )
-        .await
-        .expect("Unable to find a suitable GPU adapter!");
+    let (device, queue) = pollster::block_on(adapter.request_device(
+        &wgpu::DeviceDescriptor {
+            label: None,
+            features: (optional_features & adapter_features) | required_features,
+            limits: needed_limits,
+        },
+        trace_dir.ok().as_ref().map(std::path::Path::new),
+    ))
+    .map_err(|err| LivelyError::DeviceRequest(err.to_string()))?;
+
+    // wgpu panics on uncaught errors by default, which would take the wallpaper down with it.
+    let lost = Arc::new(AtomicBool::new(false));
+    let flag = lost.clone();
+    device.on_uncaptured_error(Box::new(move |err| {
+        log::error!("wgpu error: {}", err);
+        if matches!(err, wgpu::Error::OutOfMemory { .. }) {
+            flag.store(true, Ordering::Relaxed);
+        }
+    }));
+
+    Ok(Gpu {
+        adapter,
+        device,
+        queue,
+        lost,
+    })
+}
+
+/// Runs the wallpaper drawn by `R` until it exits. `prepare` loads what the renderers draw once
+/// signal handling is set up, so threads it spawns don't receive our signals.
+pub(crate) async fn setup<R: Renderer>(
+    config: Config,
+    prepare: impl FnOnce(&Config) -> Result<(), LivelyError>,
+) -> Result<(), LivelyError> {
+    log_startup_event("logger initialized");
+    let mut event_loop: EventLoop<Wallpaper<R>> =
+        EventLoop::try_new().map_err(|err| LivelyError::EventLoop(err.to_string()))?;
+    // Signals are blocked for this thread here, before any other thread is spawned to inherit
+    // the mask; otherwise they could be delivered to a thread that doesn't handle them.
+    let signals = Signals::new(&[
+        Signal::SIGUSR1,
+        Signal::SIGUSR2,
+        Signal::SIGINT,
+        Signal::SIGTERM,
+    ])
+    .map_err(|err| LivelyError::EventLoop(format!("failed to set up signal handling: {err}")))?;
+    prepare(&config)?;
+    // All Wayland apps start by connecting the compositor (server).
+    let conn =
+        Connection::connect_to_env().map_err(|err| LivelyError::WaylandConnect(err.to_string()))?;
+
+    // Enumerate the list of globals to get the protocols the server implements.
+    let (globals, mut event_queue) =
+        registry_queue_init(&conn).map_err(|err| LivelyError::Wayland(err.to_string()))?;
+    let qh = event_queue.handle();
+
+    // The compositor (not to be confused with the server which is commonly called the compositor) allows
+    // configuring surfaces to be presented.
+    let compositor = CompositorState::bind(&globals, &qh)
+        .map_err(|_| LivelyError::MissingProtocol("wl_compositor"))?;
+    // This app uses the wlr layer shell, which may not be available with every compositor.
+    let layer_shell = LayerShell::bind(&globals, &qh)
+        .map_err(|_| LivelyError::MissingProtocol("the wlr layer shell"))?;
+    // Used to stop rendering to outputs that are powered off; optional. Only one client can
+    // hold an output's power control, so this is opt-in: otherwise wlopm and idle daemons
+    // couldn't turn outputs off, and compositors stop frame callbacks for them anyway.
+    let power_manager = if config.watch_output_power {
+        let manager = globals.bind(&qh, 1..=1, ()).ok();
+        if manager.is_none() {
+            log::info!(
+                "wlr-output-power-management is not available, rendering regardless of DPMS state"
+            );
+        }
+        manager
+    } else {
+        None
+    };
+    let toplevel_manager = if config.pause_on_fullscreen {
+        let manager = globals.bind(&qh, 2..=3, ()).ok();
+        if manager.is_none() {
+            log::info!("wlr-foreign-toplevel-management is not available, rendering under fullscreen windows");
+        }
+        manager
+    } else {
+        None
+    };
+    let fractional_scale_manager = globals.bind(&qh, 1..=1, ()).ok();
+    let viewporter = globals.bind(&qh, 1..=1, ()).ok();
+    if fractional_scale_manager.is_none() || viewporter.is_none() {
+        log::info!("Fractional scaling is not available, using integer buffer scales");
+    }
+    // Initialize wgpu. The adapter and device are only requested once there is an output and a
+    // configured layer surface to be compatible with.
+    let backend_index = load_backend_index();
+    let instance = create_instance(backend_index);
 
     let mut w = Wallpaper {
         registry_state: RegistryState::new(&globals),
         seat_state: SeatState::new(&globals, &qh),
         output_state: OutputState::new(&globals, &qh),
         exit: false,
-        first_configure: true,
-        width: 256,
-        height: 256,
-        device,
-        wl_surface,
-        surface,
-        adapter,
-        queue,
+        fatal: None,
+        first_present: true,
+        started: Instant::now(),
+        config,
+        loop_handle: event_loop.handle(),
+        conn: conn.clone(),
+        compositor,
+        layer_shell,
+        instance,
+        backend_index,
+        backend_failures: Vec::new(),
+        failed_presents: 0,
+        device_resets: 0,
+        requirements: GpuRequirements::of::<R>(),
+        gpu: None,
+        renderers: HashMap::new(),
         shift: None,
-        layer,
+        mouse_down: false,
+        imouse: [0.0; 4],
+        targets: Vec::new(),
+        pointer_output: None,
+        power_manager,
+        fractional_scale_manager,
+        viewporter,
+        toplevel_manager,
+        toplevels: Vec::new(),
+        on_battery: false,
+        user_paused: false,
         keyboard: None,
         keyboard_focus: false,
         pointer: None,
     };
-    let handle = thread::spawn(|| {
-        use std::process;
-        println!("My pid is {}", process::id());
-        track_mouse_movement();
-        println!("Thread over");
-    });
+    // Let the output globals announce themselves; layer surfaces are created from `new_output`.
+    event_queue
+        .roundtrip(&mut w)
+        .map_err(|err| dispatch_error(&err))?;
+    w.ensure_layers(&qh);
+    // Watched even with the battery policy ignored, which the control socket can change.
+    let power_qh = qh.clone();
+    event_loop
+        .handle()
+        .insert_source(Timer::immediate(), move |_, _, w| {
+            w.set_on_battery(&power_qh, power::on_battery());
+            TimeoutAction::ToDuration(POWER_POLL_INTERVAL)
+        })
+        .map_err(|err| LivelyError::EventLoop(err.to_string()))?;
+    if let Some(delay) = renderer::with_animation(|animation| animation.delay()) {
+        let animation_qh = qh.clone();
+        event_loop
+            .handle()
+            .insert_source(Timer::from_duration(delay), move |_, _, w| {
+                match w.next_animation_frame(&animation_qh) {
+                    Some(delay) => TimeoutAction::ToDuration(delay),
+                    None => TimeoutAction::Drop,
+                }
+            })
+            .map_err(|err| LivelyError::EventLoop(err.to_string()))?;
+    }
+    let signal_qh = qh.clone();
+    event_loop
+        .handle()
+        .insert_source(signals, move |event, _, w| {
+            handle_signal(w, &signal_qh, event.signal())
+        })
+        .map_err(|err| LivelyError::EventLoop(err.to_string()))?;
+    let socket = crate::ipc::listen(&event_loop.handle(), &qh)
+        .map_err(|err| log::warn!("Control socket unavailable: {}", err))
+        .ok();
+    let wayland_source =
+        WaylandSource::new(event_queue).map_err(|err| LivelyError::Wayland(err.to_string()))?;
+    event_loop
+        .handle()
+        .insert_source(wayland_source, |_, queue, w| {
+            // Only the handlers, not the wait for events in the event loop.
+            let _span = crate::trace::span("dispatch");
+            if let Err(err) = queue.dispatch_pending(w) {
+                w.fatal = Some(dispatch_error(&err));
+                w.exit = true;
+            }
+            Ok(0)
+        })
+        .map_err(|err| LivelyError::EventLoop(err.to_string()))?;
     println!("Starting event loop");
 
+    // The mouse tracker is only started once the first frame is on screen, so that a slow
+    // libinput/udev initialization never delays the wallpaper from showing up.
+    let shutdown = Arc::new(AtomicBool::new(false));
+    let mut mouse_started = false;
+    let mut handle: Option<JoinHandle<()>> = None;
     loop {
-        event_queue.blocking_dispatch(&mut w).unwrap();
+        if let Err(err) = event_loop.dispatch(None, &mut w) {
+            log::error!("Event loop failed: {}", err);
+            w.fatal = Some(LivelyError::Wayland(format!(
+                "lost connection to the compositor: {err}"
+            )));
+            break;
+        }
         if w.exit {
             log::info!("Exiting");
-            // TODO: destroy the thread handle
             break;
         }
+        // A still image doesn't follow the pointer.
+        if !mouse_started && !w.first_present && w.config.image.is_none() {
+            mouse_started = true;
+            let shutdown = shutdown.clone();
+            let keyboard = w.config.keyboard_channel.is_some();
+            handle = Some(thread::spawn(move || {
+                use std::process;
+                println!("My pid is {}", process::id());
+                if let Err(err) = track_mouse_movement(&shutdown, keyboard) {
+                    log::error!("Mouse tracking stopped: {}", err);
+                }
+                println!("Thread over");
+            }));
+        }
+        // The wallpaper keeps running without mouse input if the tracker dies.
+        if handle.as_ref().is_some_and(|handle| handle.is_finished()) {
+            if handle.take().unwrap().join().is_err() {
+                log::error!("Mouse tracking thread panicked");
+            }
+            eprintln!("lively-rs: mouse tracking is unavailable, continuing without it");
+        }
+    }
+    crate::trace::finish();
+    drop(socket);
+    shutdown.store(true, Ordering::Relaxed);
+    if let Some(handle) = handle {
+        if handle.join().is_err() {
+            log::error!("Mouse tracking thread panicked");
+        }
+    }
+    match w.fatal.take() {
+        Some(err) => Err(err),
+        None => Ok(()),
     }
-    handle.join().unwrap();
 }
+
+/// SIGUSR1 toggles pausing, SIGUSR2 reloads the shader file, SIGINT and SIGTERM exit cleanly.
+fn handle_signal<R: Renderer>(
+    w: &mut Wallpaper<R>,
+    qh: &QueueHandle<Wallpaper<R>>,
+    signal: Signal,
+) {
+    log::info!("Received {}", signal);
+    match signal {
+        Signal::SIGUSR1 => w.set_paused(qh, !w.user_paused),
+        Signal::SIGUSR2 => match w.config.shader.clone() {
+            Some(path) => {
+                if let Err(err) = w.set_shader(qh, path) {
+                    eprintln!("{err}");
+                    log::warn!("Keeping the last working shader");
+                }
+            }
+            None => log::info!("Using the embedded shader, there is no file to reload"),
+        },
+        _ => w.exit = true,
+    }
+}
+
+/// Logs the details of a fatal Wayland error and turns it into a one-line explanation.
+fn dispatch_error(err: &DispatchError) -> LivelyError {
+    let message = match err {
+        DispatchError::Backend(WaylandError::Protocol(err)) => {
+            log::error!(
+                "Wayland protocol error on {}@{} (code {}): {}",
+                err.object_interface,
+                err.object_id,
+                err.code,
+                err.message
+            );
+            // Errors raised on the objects we create and drive ourselves are almost always
+            // requests we sent wrongly; anything else is more likely on the compositor side.
+            let culprit = match err.object_interface.as_str() {
+                "zwlr_layer_surface_v1" | "zwlr_layer_shell_v1" | "wl_surface" | "wl_callback" => {
+                    "this is most likely a lively-rs bug, please report it together with the \
+                     output of running with WAYLAND_DEBUG=1"
+                }
+                _ => "this is most likely a compositor bug or an unsupported protocol version",
+            };
+            format!("{}: {} — {}", err.object_interface, err.message, culprit)
+        }
+        DispatchError::Backend(WaylandError::Io(err)) => {
+            log::error!("Lost connection to the compositor: {}", err);
+            format!("lost connection to the compositor: {err}")
+        }
+        DispatchError::BadMessage {
+            sender_id,
+            interface,
+            opcode,
+        } => {
+            log::error!(
+                "Compositor sent a malformed message to {}@{} (opcode {})",
+                interface,
+                sender_id,
+                opcode
+            );
+            format!("{err} — this is most likely a compositor bug")
+        }
+    };
+    LivelyError::Wayland(message)
+}
+
+/// Just enough state to learn which outputs exist, for `--list-outputs`.
+struct OutputLister {
+    registry_state: RegistryState,
+    output_state: OutputState,
+}
+
+impl OutputHandler for OutputLister {
+    fn output_state(&mut self) -> &mut OutputState {
+        &mut self.output_state
+    }
+    fn new_output(&mut self, _: &Connection, _: &QueueHandle<Self>, _: wl_output::WlOutput) {}
+    fn update_output(&mut self, _: &Connection, _: &QueueHandle<Self>, _: wl_output::WlOutput) {}
+    fn output_destroyed(&mut self, _: &Connection, _: &QueueHandle<Self>, _: wl_output::WlOutput) {}
+}
+
+impl ProvidesRegistryState for OutputLister {
+    fn registry(&mut self) -> &mut RegistryState {
+        &mut self.registry_state
+    }
+    registry_handlers![OutputState];
+}
+
+/// Prints the outputs the compositor announces, with the names `--output` accepts.
+pub(crate) fn list_outputs() -> Result<(), LivelyError> {
+    let conn =
+        Connection::connect_to_env().map_err(|err| LivelyError::WaylandConnect(err.to_string()))?;
+    let (globals, mut event_queue) =
+        registry_queue_init(&conn).map_err(|err| LivelyError::Wayland(err.to_string()))?;
+    let qh = event_queue.handle();
+    let mut lister = OutputLister {
+        registry_state: RegistryState::new(&globals),
+        output_state: OutputState::new(&globals, &qh),
+    };
+    // The first roundtrip binds the outputs, the second receives their properties.
+    for _ in 0..2 {
+        event_queue
+            .roundtrip(&mut lister)
+            .map_err(|err| dispatch_error(&err))?;
+    }
+    for output in lister.output_state.outputs() {
+        let Some(info) = lister.output_state.info(&output) else {
+            continue;
+        };
+        let size = info
+            .logical_size
+            .map(|(w, h)| format!("{w}x{h}"))
+            .unwrap_or_else(|| "?".to_string());
+        let (x, y) = info.logical_position.unwrap_or(info.location);
+        println!(
+            "{}\t{} at {},{} scale {}\t{}",
+            info.name.as_deref().unwrap_or("<unnamed>"),
+            size,
+            x,
+            y,
+            info.scale_factor,
+            info.description
+                .unwrap_or_else(|| format!("{} {}", info.make, info.model))
+        );
+    }
+    Ok(())
+}
+
 struct Interface;
 
 impl LibinputInterface for Interface {
@@ -255,34 +1221,72 @@ impl LibinputInterface for Interface {
     }
 }
 
-fn track_mouse_movement() {
+/// How long the mouse tracker blocks in poll() before checking for shutdown.
+const MOUSE_POLL_TIMEOUT_MS: i32 = 250;
+
+/// Follows pointer motion from all input devices and, with `keyboard`, the keys held down, which
+/// works whether or not a layer has keyboard focus.
+fn track_mouse_movement(shutdown: &AtomicBool, keyboard: bool) -> Result<(), String> {
     let mut input = Libinput::new_with_udev(Interface);
-    input.udev_assign_seat("seat0").unwrap();
+    input
+        .udev_assign_seat("seat0")
+        .map_err(|()| "failed to assign seat0 to libinput".to_string())?;
     let pollfd = PollFd::new(input.as_raw_fd(), PollFlags::POLLIN);
-    while poll(&mut [pollfd], -1).is_ok() {
-        input.dispatch().unwrap();
+    log_startup_event("mouse tracker ready");
+    while !shutdown.load(Ordering::Relaxed) {
+        if MOUSE_SUSPENDED.load(Ordering::Relaxed) {
+            // Release the input devices and stop polling them while the screens are off.
+            log::debug!("Suspending mouse tracking");
+            input.suspend();
+            while MOUSE_SUSPENDED.load(Ordering::Relaxed) && !shutdown.load(Ordering::Relaxed) {
+                thread::park_timeout(Duration::from_millis(MOUSE_POLL_TIMEOUT_MS as u64));
+            }
+            log::debug!("Resuming mouse tracking");
+            input
+                .resume()
+                .map_err(|()| "failed to resume libinput".to_string())?;
+            continue;
+        }
+        match poll(&mut [pollfd], MOUSE_POLL_TIMEOUT_MS) {
+            Ok(0) | Err(Errno::EINTR) => continue,
+            Ok(_) => {}
+            Err(err) => return Err(format!("polling libinput failed: {err}")),
+        }
+        input
+            .dispatch()
+            .map_err(|err| format!("libinput dispatch failed: {err}"))?;
         for event in &mut input {
-            if let input::event::Event::Pointer(LibinputPointerEvent::Motion(pointer_event)) =
-                &event
-            {
-                // println!("({}, {})", pointer_event.dx(), pointer_event.dy());
-                // wait for lock
-                let mut pos = POINTER_POS.lock().unwrap();
-                (*pos).0 += pointer_event.dx();
-                (*pos).1 += pointer_event.dy();
-                drop(pos);
+            match &event {
+                input::event::Event::Pointer(LibinputPointerEvent::Motion(pointer_event)) => {
+                    // println!("({}, {})", pointer_event.dx(), pointer_event.dy());
+                    // wait for lock
+                    let scale = POINTER_SCALE.load(Ordering::Relaxed) as f64;
+                    let mut pos = POINTER_POS.lock().unwrap();
+                    pos.0 += pointer_event.dx() / scale;
+                    pos.1 += pointer_event.dy() / scale;
+                    drop(pos);
+                }
+                input::event::Event::Keyboard(KeyboardEvent::Key(key_event)) if keyboard => {
+                    let pressed = key_event.key_state() == KeyState::Pressed;
+                    crate::keyboard::set_key(key_event.key(), pressed);
+                }
+                _ => {}
             }
         }
     }
     println!("returning from mouse");
+    Ok(())
 }
-delegate_compositor!(Wallpaper);
-delegate_output!(Wallpaper);
+delegate_compositor!(@<R: Renderer> Wallpaper<R>);
+delegate_output!(@<R: Renderer> Wallpaper<R>);
+
+delegate_seat!(@<R: Renderer> Wallpaper<R>);
+delegate_keyboard!(@<R: Renderer> Wallpaper<R>);
+delegate_pointer!(@<R: Renderer> Wallpaper<R>);
 
-delegate_seat!(Wallpaper);
-delegate_keyboard!(Wallpaper);
-delegate_pointer!(Wallpaper);
+delegate_layer!(@<R: Renderer> Wallpaper<R>);
 
-delegate_layer!(Wallpaper);
+delegate_registry!(@<R: Renderer> Wallpaper<R>);
 
-delegate_registry!(Wallpaper);
+delegate_output!(OutputLister);
+delegate_registry!(OutputLister);
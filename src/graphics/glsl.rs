@@ -0,0 +1,148 @@
+// GLSL fragment shaders, translated to WGSL by naga so they go through the same validation and
+// pipeline as WGSL ones. The vertex stage is always the built-in full-surface triangle.
+use naga::front::glsl;
+use naga::valid::{Capabilities, ValidationFlags, Validator};
+
+/// Replaces the shader's own `#version`; naga only accepts 440 and 450 anyway.
+pub const VERSION: &str = "#version 450 core\n";
+
+/// The wallpaper's uniforms (see [`super::pipeline::Uniforms`]) as a GLSL block, under the same
+/// name as in WGSL shaders.
+pub const UNIFORMS: &str = "layout(set = 0, binding = 0) uniform Uniforms {
+    vec2 mouse;
+    float time;
+    float srgb;
+    vec2 resolution;
+    vec4 imouse;
+    float loudness;
+    float last_key;
+} uniforms;
+";
+
+/// The image channels (see [`super::channels::ChannelBindings`]) as `sampler2D`s.
+pub const CHANNELS: &str = "layout(set = 1, binding = 0) uniform texture2D lively_channel0;
+layout(set = 1, binding = 1) uniform sampler lively_sampler0;
+layout(set = 1, binding = 2) uniform texture2D lively_channel1;
+layout(set = 1, binding = 3) uniform sampler lively_sampler1;
+layout(set = 1, binding = 4) uniform texture2D lively_channel2;
+layout(set = 1, binding = 5) uniform sampler lively_sampler2;
+layout(set = 1, binding = 6) uniform texture2D lively_channel3;
+layout(set = 1, binding = 7) uniform sampler lively_sampler3;
+#define iChannel0 sampler2D(lively_channel0, lively_sampler0)
+#define iChannel1 sampler2D(lively_channel1, lively_sampler1)
+#define iChannel2 sampler2D(lively_channel2, lively_sampler2)
+#define iChannel3 sampler2D(lively_channel3, lively_sampler3)
+";
+
+/// The previous frame (see [`super::pipeline::Feedback`]) as a `sampler2D`.
+const PREV_FRAME: &str = "layout(set = 3, binding = 0) uniform texture2D lively_prev_frame;
+layout(set = 3, binding = 1) uniform sampler lively_prev_frame_sampler;
+#define iPrevFrame sampler2D(lively_prev_frame, lively_prev_frame_sampler)
+";
+
+const VERTEX: &str = "
+@vertex
+fn vs_main(@builtin(vertex_index) in_vertex_index: u32) -> @builtin(position) vec4<f32> {
+    // A single triangle covering the whole surface.
+    let x = f32(i32(in_vertex_index & 1u) * 4 - 1);
+    let y = f32(i32(in_vertex_index >> 1u) * 4 - 1);
+    return vec4<f32>(x, y, 0.0, 1.0);
+}
+";
+
+/// Translates a GLSL fragment shader with a `main` function to a WGSL wallpaper shader with
+/// `vs_main` and `fs_main`. The uniform block, `iChannel0` to `iChannel3` and, if it uses it,
+/// `iPrevFrame` are declared for it.
+pub fn to_wgsl(name: &str, code: &str) -> Result<String, String> {
+    // Blanked rather than removed so line numbers stay the same.
+    let code: Vec<&str> = code
+        .lines()
+        .map(|line| {
+            if line.trim_start().starts_with("#version") {
+                ""
+            } else {
+                line
+            }
+        })
+        .collect();
+    let code = code.join("\n");
+    let header = format!("{VERSION}{UNIFORMS}{CHANNELS}{}", prev_frame(&code));
+    translate(name, &header, &code, "")
+}
+
+/// The declaration of `iPrevFrame` if `code` mentions it. Other shaders go without, as binding
+/// the previous frame costs a copy of every frame.
+pub fn prev_frame(code: &str) -> &'static str {
+    if code.contains("iPrevFrame") {
+        PREV_FRAME
+    } else {
+        ""
+    }
+}
+
+/// Translates `header`, `code` and `footer` as one fragment shader, reporting errors in `code`
+/// at their position in it.
+pub fn translate(name: &str, header: &str, code: &str, footer: &str) -> Result<String, String> {
+    let source = format!("{header}{code}{footer}");
+    let mut module = glsl::Parser::default()
+        .parse(&glsl::Options::from(naga::ShaderStage::Fragment), &source)
+        .map_err(|errors| report(name, &source, header, &errors))?;
+    for entry_point in &mut module.entry_points {
+        entry_point.name = "fs_main".to_string();
+    }
+    let info = Validator::new(ValidationFlags::all(), Capabilities::all())
+        .validate(&module)
+        .map_err(|err| err.emit_to_string_with_path(&source, name))?;
+    let wgsl =
+        naga::back::wgsl::write_string(&module, &info, naga::back::wgsl::WriterFlags::empty())
+            .map_err(|err| format!("{name}: failed to translate to WGSL: {err}"))?;
+    Ok(wgsl + VERTEX)
+}
+
+/// Formats naga's errors as `file:line:column: message`, with positions in the user's file
+/// rather than the wrapped source.
+fn report(name: &str, source: &str, header: &str, errors: &[glsl::Error]) -> String {
+    let header_lines = header.lines().count() as u32;
+    errors
+        .iter()
+        .map(|err| {
+            let location = err.meta.location(source);
+            if err.meta.is_defined() && location.line_number > header_lines {
+                format!(
+                    "{}:{}:{}: {}",
+                    name,
+                    location.line_number - header_lines,
+                    location.line_position,
+                    err.kind
+                )
+            } else {
+                format!("{}: {}", name, err.kind)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn translates_a_fragment_shader() {
+        let code = "#version 300 es\nvoid main() {\n    gl_FragDepth = uniforms.time;\n}\n";
+        let wgsl = to_wgsl("ok.frag", code).unwrap();
+        assert!(wgsl.contains("fn fs_main("));
+        assert!(wgsl.contains("fn vs_main("));
+    }
+
+    #[test]
+    fn reports_errors_at_the_users_line() {
+        // Line 3 of the user's file, after a #version line that is blanked out.
+        let code = "#version 330\nvoid main() {\n    float x = undefined_name;\n}\n";
+        let err = to_wgsl("broken.frag", code).unwrap_err();
+        assert!(
+            err.starts_with("broken.frag:3:"),
+            "error not on the user's line: {err}"
+        );
+    }
+}
@@ -1 +1,6 @@
+pub mod channels;
 pub mod framework;
+pub mod glsl;
+pub mod pipeline;
+pub mod renderer;
+pub mod shadertoy;
@@ -0,0 +1,772 @@
+use super::channels::{ChannelBindings, ChannelImage};
+use super::{glsl, shadertoy};
+use crate::config::{PassConfig, Scaling, SCREEN};
+use crate::pacing::Pacer;
+use crate::trace;
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// How often the shader file is checked for changes.
+const WATCH_INTERVAL: Duration = Duration::from_millis(250);
+
+/// WGSL source of the wallpaper shader and where it came from.
+pub struct ShaderSource {
+    pub name: String,
+    pub code: String,
+    /// Whether the shader samples `iPrevFrame`, which makes every frame go through a buffer.
+    pub prev_frame: bool,
+}
+
+impl ShaderSource {
+    /// The shader compiled into the binary, used when no shader file is given.
+    pub fn embedded() -> Self {
+        Self {
+            name: "shader.wgsl".to_string(),
+            code: include_str!("../shader.wgsl").to_string(),
+            prev_frame: false,
+        }
+    }
+
+    /// The built-in shader of the image mode, drawing channel 0 with `scaling` on `background`.
+    pub fn image(scaling: Scaling, background: [f64; 4]) -> Self {
+        let [r, g, b, a] = background;
+        let constants = format!(
+            "fn scaling() -> u32 {{ return {}u; }}\n\
+             fn background() -> vec4<f32> {{ return vec4<f32>({r:.6}, {g:.6}, {b:.6}, {a:.6}); }}\n",
+            scaling as u32
+        );
+        Self {
+            name: "image.wgsl".to_string(),
+            code: constants + include_str!("../image.wgsl"),
+            prev_frame: false,
+        }
+    }
+
+    /// Reads a shader file and checks that it compiles, so a broken shader is reported with its
+    /// file name instead of panicking inside wgpu. `.frag` and `.glsl` files are GLSL and
+    /// translated to WGSL first; with `shadertoy`, everything but `.wgsl` files is Shadertoy
+    /// GLSL.
+    pub fn load(path: &Path, shadertoy: bool) -> Result<Self, String> {
+        Self::load_with_entry_point(path, shadertoy, "fs_main")
+    }
+
+    /// Like [`ShaderSource::load`], for a shader drawing with `entry_point`.
+    pub fn load_with_entry_point(
+        path: &Path,
+        shadertoy: bool,
+        entry_point: &str,
+    ) -> Result<Self, String> {
+        let name = path.display().to_string();
+        let mut code = std::fs::read_to_string(path)
+            .map_err(|err| format!("Failed to read shader {}: {}", name, err))?;
+        let extension = path.extension().and_then(|ext| ext.to_str());
+        if shadertoy && extension != Some("wgsl") {
+            code = shadertoy::to_wgsl(&name, &code)?;
+        } else if matches!(extension, Some("frag" | "glsl")) {
+            code = glsl::to_wgsl(&name, &code)?;
+        }
+        let prev_frame = validate(&name, &code, entry_point)?;
+        Ok(Self {
+            name,
+            code,
+            prev_frame,
+        })
+    }
+}
+
+/// Checks that the shader compiles and has the entry points, and returns whether it binds the
+/// previous frame.
+fn validate(name: &str, code: &str, fragment_entry_point: &str) -> Result<bool, String> {
+    let module = naga::front::wgsl::parse_str(code)
+        .map_err(|err| err.emit_to_string_with_path(code, name))?;
+    naga::valid::Validator::new(
+        naga::valid::ValidationFlags::all(),
+        naga::valid::Capabilities::all(),
+    )
+    .validate(&module)
+    .map_err(|err| err.emit_to_string_with_path(code, name))?;
+    for entry_point in ["vs_main", fragment_entry_point] {
+        if !module.entry_points.iter().any(|ep| ep.name == entry_point) {
+            return Err(format!("{name}: missing entry point `{entry_point}`"));
+        }
+    }
+    let prev_frame = module.global_variables.iter().any(|(_, var)| {
+        var.binding
+            .as_ref()
+            .is_some_and(|binding| binding.group == PREV_FRAME_GROUP)
+    });
+    Ok(prev_frame)
+}
+
+/// Per-frame values available to shaders as `@group(0) @binding(0) var<uniform>`:
+///
+/// ```wgsl
+/// struct Uniforms {
+///     mouse: vec2<f32>,
+///     time: f32,
+///     srgb: f32,
+///     resolution: vec2<f32>,
+///     imouse: vec4<f32>,
+///     loudness: f32,
+///     last_key: f32,
+/// };
+/// ```
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Uniforms {
+    /// Pointer position in 0..1 across the surface, from the top left.
+    pub mouse: [f32; 2],
+    /// Seconds since startup, wrapped every [`TIME_WRAP`] seconds.
+    pub time: f32,
+    /// 1.0 when the surface format is sRGB and the GPU encodes the shader's output, 0.0 when
+    /// the output is stored as is and the shader has to apply gamma itself.
+    pub srgb: f32,
+    /// Size of the surface in pixels.
+    pub resolution: [f32; 2],
+    pub _padding2: [f32; 2],
+    /// Shadertoy's `iMouse`, in pixels from the bottom left: xy is the pointer position while the
+    /// left button is held, zw where it was pressed. z turns negative once the button is released
+    /// and w is only positive during the frame of the click.
+    pub imouse: [f32; 4],
+    /// RMS loudness of the captured audio in 0..1, 0 without the `audio` feature or channel.
+    pub loudness: f32,
+    /// `time` of the last key press with `--keyboard-input`, -1 before the first one.
+    pub last_key: f32,
+    pub _padding3: [f32; 2],
+}
+
+// SAFETY: `Uniforms` is `repr(C)`, made of `f32`s only and has no padding bytes.
+unsafe impl bytemuck::Zeroable for Uniforms {}
+unsafe impl bytemuck::Pod for Uniforms {}
+
+/// `time` wraps around after this many seconds. Elapsed time is kept as f64 on the host, but an
+/// f32 in the shader loses sub-millisecond precision after a few hours of uptime, which shows
+/// up as stuttering animations; wrapping hourly keeps it below 0.25ms.
+pub const TIME_WRAP: f64 = 3600.0;
+
+/// The `time` uniform for `since_start` since startup.
+pub fn shader_time(since_start: Duration) -> f32 {
+    (since_start.as_secs_f64() % TIME_WRAP) as f32
+}
+
+/// Format of the buffers passes render to; float, so that feedback effects don't band.
+const BUFFER_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// Bind group of the previous frame, `iPrevFrame` at binding 0 and its sampler at 1:
+///
+/// ```wgsl
+/// @group(3) @binding(0) var iPrevFrame: texture_2d<f32>;
+/// @group(3) @binding(1) var iPrevFrame_sampler: sampler;
+/// ```
+pub const PREV_FRAME_GROUP: u32 = 3;
+
+/// A render pass as configured, with buffers numbered in the order passes first render to them.
+pub struct PassSource {
+    /// The pass's own shader, None to draw with the main shader.
+    pub shader: Option<ShaderSource>,
+    pub entry_point: String,
+    /// Buffer rendered to, None for the screen.
+    pub output: Option<usize>,
+    /// Buffers sampled in group 2.
+    pub inputs: Vec<usize>,
+}
+
+impl PassSource {
+    /// The single pass used without configured passes: the main shader's `fs_main` straight to
+    /// the screen.
+    pub fn screen() -> Self {
+        Self {
+            shader: None,
+            entry_point: "fs_main".to_string(),
+            output: None,
+            inputs: Vec::new(),
+        }
+    }
+
+    /// Loads the shaders of the configured passes, or gives the single screen pass if there are
+    /// none. The config has already checked that the passes fit together.
+    pub fn load_all(passes: &[PassConfig]) -> Result<Vec<Self>, String> {
+        if passes.is_empty() {
+            return Ok(vec![Self::screen()]);
+        }
+        let mut buffers: Vec<&str> = Vec::new();
+        for pass in passes {
+            if pass.output != SCREEN && !buffers.contains(&pass.output.as_str()) {
+                buffers.push(&pass.output);
+            }
+        }
+        let buffer = |name: &str| buffers.iter().position(|buffer| *buffer == name);
+        passes
+            .iter()
+            .map(|pass| {
+                let shader = pass
+                    .shader
+                    .as_deref()
+                    .map(|path| ShaderSource::load_with_entry_point(path, false, &pass.entry_point))
+                    .transpose()?;
+                Ok(Self {
+                    shader,
+                    entry_point: pass.entry_point.clone(),
+                    output: buffer(&pass.output),
+                    inputs: pass
+                        .inputs
+                        .iter()
+                        .filter_map(|input| buffer(input))
+                        .collect(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// A pass of the built pipeline.
+pub struct Pass {
+    /// Name of the pass's trace span, after its position and entry point.
+    pub label: String,
+    pub render_pipeline: wgpu::RenderPipeline,
+    pub output: Option<usize>,
+    pub inputs: Vec<usize>,
+    /// Layout of group 2, None for passes without inputs.
+    pub inputs_layout: Option<wgpu::BindGroupLayout>,
+}
+
+/// For shaders sampling `iPrevFrame`: the screen pass renders to a buffer, whose previous
+/// contents are bound in [`PREV_FRAME_GROUP`], and the buffer is then copied to the swapchain.
+/// Going through a buffer keeps the swapchain free of copy usages, which not every surface has,
+/// and makes the previous frame filterable whatever the swapchain format.
+pub struct Feedback {
+    /// The buffer the screen pass renders to.
+    pub frame: usize,
+    pub layout: wgpu::BindGroupLayout,
+    pub blit_pipeline: wgpu::RenderPipeline,
+}
+
+/// The render pipelines for the wallpaper's passes, built once per swapchain format.
+pub struct WallpaperPipeline {
+    pub format: wgpu::TextureFormat,
+    pub passes: Vec<Pass>,
+    pub feedback: Option<Feedback>,
+    /// Number of buffers the passes render to.
+    pub buffer_count: usize,
+    pub buffer_sampler: wgpu::Sampler,
+    pub uniform_buffer: wgpu::Buffer,
+    pub bind_group: wgpu::BindGroup,
+    pub channels: ChannelBindings,
+}
+
+impl WallpaperPipeline {
+    /// Builds the pipeline, returning wgpu's validation errors instead of panicking on them.
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        format: wgpu::TextureFormat,
+        source: &ShaderSource,
+        passes: &[PassSource],
+        channels: &[Option<ChannelImage>],
+    ) -> Result<Self, String> {
+        log::info!(
+            "Building render pipeline for {} ({:?})",
+            source.name,
+            format
+        );
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(&source.name),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(&source.code)),
+        });
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("uniforms"),
+            size: std::mem::size_of::<Uniforms>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("uniforms"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: wgpu::BufferSize::new(
+                        std::mem::size_of::<Uniforms>() as wgpu::BufferAddress
+                    ),
+                },
+                count: None,
+            }],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("uniforms"),
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+        });
+        let channels = ChannelBindings::new(device, queue, channels);
+        let prev_frame = source.prev_frame
+            || passes
+                .iter()
+                .filter_map(|pass| pass.shader.as_ref())
+                .any(|source| source.prev_frame);
+        let frame_layout = prev_frame.then(|| input_layout(device, 1));
+        let passes: Vec<Pass> = passes
+            .iter()
+            .enumerate()
+            .map(|(index, pass)| {
+                let own_shader = pass.shader.as_ref().map(|source| {
+                    device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                        label: Some(&source.name),
+                        source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(&source.code)),
+                    })
+                });
+                // Group 2 can't be left out when group 3 follows it.
+                let inputs_layout = (prev_frame || !pass.inputs.is_empty())
+                    .then(|| input_layout(device, pass.inputs.len() as u32));
+                let mut bind_group_layouts = vec![&bind_group_layout, &channels.layout];
+                bind_group_layouts.extend(&inputs_layout);
+                bind_group_layouts.extend(&frame_layout);
+                let pipeline_layout =
+                    device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                        label: None,
+                        bind_group_layouts: &bind_group_layouts,
+                        push_constant_ranges: &[],
+                    });
+                let module = own_shader.as_ref().unwrap_or(&shader);
+                let target_format = if pass.output.is_some() || prev_frame {
+                    BUFFER_FORMAT
+                } else {
+                    format
+                };
+                let render_pipeline =
+                    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                        label: Some(&pass.entry_point),
+                        layout: Some(&pipeline_layout),
+                        vertex: wgpu::VertexState {
+                            module,
+                            entry_point: "vs_main",
+                            buffers: &[],
+                        },
+                        fragment: Some(wgpu::FragmentState {
+                            module,
+                            entry_point: &pass.entry_point,
+                            targets: &[Some(target_format.into())],
+                        }),
+                        primitive: wgpu::PrimitiveState::default(),
+                        depth_stencil: None,
+                        multisample: wgpu::MultisampleState::default(),
+                        multiview: None,
+                    });
+                Pass {
+                    label: format!("pass {} ({})", index, pass.entry_point),
+                    render_pipeline,
+                    output: pass.output,
+                    inputs: pass.inputs.clone(),
+                    inputs_layout,
+                }
+            })
+            .collect();
+        let buffer_count = passes
+            .iter()
+            .filter_map(|pass| pass.output)
+            .max()
+            .map_or(0, |last| last + 1);
+        let feedback = frame_layout.map(|layout| Feedback {
+            frame: buffer_count,
+            blit_pipeline: blit_pipeline(device, &layout, format),
+            layout,
+        });
+        if let Some(err) = pollster::block_on(device.pop_error_scope()) {
+            return Err(format!(
+                "Failed to build pipeline for {}: {}",
+                source.name, err
+            ));
+        }
+        let buffer_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("pass buffers"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        Ok(Self {
+            format,
+            buffer_count: buffer_count + feedback.is_some() as usize,
+            passes,
+            feedback,
+            buffer_sampler,
+            uniform_buffer,
+            bind_group,
+            channels,
+        })
+    }
+
+    /// Records all passes into `encoder`, the last one drawing to `screen`. Each pass renders
+    /// to the other texture of its buffer, which then becomes the buffer's latest contents.
+    pub fn encode(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        buffers: &mut PassBuffers,
+        screen: &wgpu::TextureView,
+        clear_color: wgpu::Color,
+    ) {
+        // Bound before the screen pass renders, so it is still last frame's.
+        let prev_frame = self.feedback.as_ref().map(|feedback| {
+            self.bind_buffers(device, &feedback.layout, buffers, &[feedback.frame])
+        });
+        let frame = self.feedback.as_ref().map(|feedback| feedback.frame);
+        for pass in &self.passes {
+            let _span = trace::span_with(|| pass.label.clone());
+            let inputs = pass
+                .inputs_layout
+                .as_ref()
+                .map(|layout| self.bind_buffers(device, layout, buffers, &pass.inputs));
+            let (view, clear) = match (pass.output, frame) {
+                (Some(buffer), _) => (buffers.next(buffer), wgpu::Color::TRANSPARENT),
+                (None, Some(frame)) => (buffers.next(frame), clear_color),
+                (None, None) => (screen, clear_color),
+            };
+            {
+                let mut rpass = begin_pass(encoder, view, clear);
+                rpass.set_pipeline(&pass.render_pipeline);
+                rpass.set_bind_group(0, &self.bind_group, &[]);
+                rpass.set_bind_group(1, &self.channels.bind_group, &[]);
+                if let Some(inputs) = &inputs {
+                    rpass.set_bind_group(2, inputs, &[]);
+                }
+                if let Some(prev_frame) = &prev_frame {
+                    rpass.set_bind_group(PREV_FRAME_GROUP, prev_frame, &[]);
+                }
+                rpass.draw(0..3, 0..1);
+            }
+            if let Some(buffer) = pass.output.or(frame) {
+                buffers.flip(buffer);
+            }
+        }
+        if let Some(feedback) = &self.feedback {
+            let _span = trace::span("pass blit");
+            let frame = self.bind_buffers(device, &feedback.layout, buffers, &[feedback.frame]);
+            let mut rpass = begin_pass(encoder, screen, clear_color);
+            rpass.set_pipeline(&feedback.blit_pipeline);
+            rpass.set_bind_group(0, &frame, &[]);
+            rpass.draw(0..3, 0..1);
+        }
+    }
+
+    /// A bind group of the latest contents of `inputs`, laid out by [`input_layout`].
+    fn bind_buffers(
+        &self,
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        buffers: &PassBuffers,
+        inputs: &[usize],
+    ) -> wgpu::BindGroup {
+        let entries: Vec<wgpu::BindGroupEntry> = inputs
+            .iter()
+            .zip(0..)
+            .flat_map(|(&buffer, input)| {
+                [
+                    wgpu::BindGroupEntry {
+                        binding: 2 * input,
+                        resource: wgpu::BindingResource::TextureView(buffers.latest(buffer)),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2 * input + 1,
+                        resource: wgpu::BindingResource::Sampler(&self.buffer_sampler),
+                    },
+                ]
+            })
+            .collect();
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("pass inputs"),
+            layout,
+            entries: &entries,
+        })
+    }
+}
+
+fn begin_pass<'a>(
+    encoder: &'a mut wgpu::CommandEncoder,
+    view: &'a wgpu::TextureView,
+    clear: wgpu::Color,
+) -> wgpu::RenderPass<'a> {
+    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: None,
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(clear),
+                store: true,
+            },
+        })],
+        depth_stencil_attachment: None,
+    })
+}
+
+/// The pipeline copying the frame buffer to the swapchain, see [`Feedback`].
+fn blit_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    format: wgpu::TextureFormat,
+) -> wgpu::RenderPipeline {
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("blit.wgsl"),
+        source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("../blit.wgsl"))),
+    });
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("blit"),
+        bind_group_layouts: &[layout],
+        push_constant_ranges: &[],
+    });
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("blit"),
+        layout: Some(&pipeline_layout),
+        vertex: wgpu::VertexState {
+            module: &shader,
+            entry_point: "vs_main",
+            buffers: &[],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: &shader,
+            entry_point: "fs_main",
+            targets: &[Some(format.into())],
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+    })
+}
+
+/// Group 2 of a pass with `count` inputs, buffer `n` as a texture at binding `2n` and its
+/// sampler at `2n + 1`, like the channels.
+fn input_layout(device: &wgpu::Device, count: u32) -> wgpu::BindGroupLayout {
+    let entries: Vec<wgpu::BindGroupLayoutEntry> = (0..count)
+        .flat_map(|input| {
+            [
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2 * input,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2 * input + 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ]
+        })
+        .collect();
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("pass inputs"),
+        entries: &entries,
+    })
+}
+
+/// The buffers of one layer, sized like its swapchain. Each has two textures, so a pass can
+/// sample the previous frame of the buffer it renders to.
+pub struct PassBuffers {
+    pub size: (u32, u32),
+    textures: Vec<[(wgpu::Texture, wgpu::TextureView); 2]>,
+    /// Which of the two textures of each buffer holds its latest contents.
+    latest: Vec<usize>,
+}
+
+impl PassBuffers {
+    /// Allocates `count` buffers, cleared to transparent black.
+    pub fn new(device: &wgpu::Device, count: usize, (width, height): (u32, u32)) -> Self {
+        let texture = || {
+            let texture = device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("pass buffer"),
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: BUFFER_FORMAT,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                    | wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            });
+            let view = texture.create_view(&Default::default());
+            (texture, view)
+        };
+        Self {
+            size: (width, height),
+            textures: (0..count).map(|_| [texture(), texture()]).collect(),
+            latest: vec![0; count],
+        }
+    }
+
+    fn latest(&self, buffer: usize) -> &wgpu::TextureView {
+        &self.textures[buffer][self.latest[buffer]].1
+    }
+
+    fn next(&self, buffer: usize) -> &wgpu::TextureView {
+        &self.textures[buffer][1 - self.latest[buffer]].1
+    }
+
+    fn flip(&mut self, buffer: usize) {
+        self.latest[buffer] = 1 - self.latest[buffer];
+    }
+}
+
+/// A thread watching a shader file, stopped when this is dropped.
+pub struct ShaderWatcher {
+    /// Every version of the file that compiles.
+    pub updates: Receiver<ShaderSource>,
+    stop: Arc<AtomicBool>,
+}
+
+impl Drop for ShaderWatcher {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Polls `path` for modifications and sends every version that compiles to the returned
+/// watcher. A change is only picked up once the file has stopped changing for a poll interval,
+/// so editors that write in several steps and rapid successive saves cause a single reload.
+pub fn watch_shader(path: PathBuf, shadertoy: bool) -> ShaderWatcher {
+    let modified = |path: &Path| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+    let (updates, rx) = mpsc::channel();
+    let stop = Arc::new(AtomicBool::new(false));
+    let stopped = stop.clone();
+    thread::spawn(move || {
+        let mut loaded: Option<SystemTime> = modified(&path);
+        let mut pending: Option<SystemTime> = None;
+        let mut pacer = match Pacer::new(WATCH_INTERVAL) {
+            Ok(pacer) => pacer,
+            Err(err) => {
+                log::warn!("Not watching {} for changes: {}", path.display(), err);
+                return;
+            }
+        };
+        loop {
+            pacer.wait();
+            // Switching shaders replaces the watcher; this one is done.
+            if stopped.load(Ordering::Relaxed) {
+                return;
+            }
+            let current = modified(&path);
+            if current == loaded {
+                pending = None;
+                continue;
+            }
+            if current != pending {
+                // Still being written, wait for it to settle.
+                pending = current;
+                continue;
+            }
+            loaded = current;
+            pending = None;
+            match ShaderSource::load(&path, shadertoy) {
+                Ok(source) => {
+                    log::info!("{} changed, reloading", path.display());
+                    if updates.send(source).is_err() {
+                        return;
+                    }
+                }
+                Err(err) => {
+                    eprintln!("{err}");
+                    log::warn!("Keeping the last working shader");
+                }
+            }
+        }
+    });
+    ShaderWatcher { updates: rx, stop }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::mem::{offset_of, size_of};
+
+    #[test]
+    fn uniforms_match_the_wgsl_layout() {
+        // WGSL aligns vec2 to 8 and vec4 to 16 bytes, and rounds the struct up to 16.
+        assert_eq!(size_of::<Uniforms>(), 64);
+        assert_eq!(offset_of!(Uniforms, mouse), 0);
+        assert_eq!(offset_of!(Uniforms, time), 8);
+        assert_eq!(offset_of!(Uniforms, srgb), 12);
+        assert_eq!(offset_of!(Uniforms, resolution), 16);
+        assert_eq!(offset_of!(Uniforms, imouse), 32);
+        assert_eq!(offset_of!(Uniforms, loudness), 48);
+        assert_eq!(offset_of!(Uniforms, last_key), 52);
+    }
+
+    #[test]
+    fn mouse_glow_reads_the_uniforms_in_the_fragment_stage() {
+        let code = include_str!("../../shaders/mouse-glow.wgsl");
+        assert_eq!(validate("mouse-glow.wgsl", code, "fs_main"), Ok(false));
+
+        let module = naga::front::wgsl::parse_str(code).unwrap();
+        let info = naga::valid::Validator::new(
+            naga::valid::ValidationFlags::all(),
+            naga::valid::Capabilities::all(),
+        )
+        .validate(&module)
+        .unwrap();
+        let (handle, uniforms) = module
+            .global_variables
+            .iter()
+            .find(|(_, var)| var.space == naga::AddressSpace::Uniform)
+            .unwrap();
+        // The shader's struct has to be laid out like the buffer we upload.
+        let naga::TypeInner::Struct { members, span } = &module.types[uniforms.ty].inner else {
+            panic!("uniforms aren't a struct");
+        };
+        assert_eq!(*span as usize, size_of::<Uniforms>());
+        let offsets: Vec<(&str, usize)> = members
+            .iter()
+            .map(|member| (member.name.as_deref().unwrap(), member.offset as usize))
+            .collect();
+        assert_eq!(
+            offsets,
+            [
+                ("mouse", offset_of!(Uniforms, mouse)),
+                ("time", offset_of!(Uniforms, time)),
+                ("srgb", offset_of!(Uniforms, srgb)),
+                ("resolution", offset_of!(Uniforms, resolution)),
+                ("imouse", offset_of!(Uniforms, imouse)),
+                ("loudness", offset_of!(Uniforms, loudness)),
+                ("last_key", offset_of!(Uniforms, last_key)),
+            ]
+        );
+        let fragment = module
+            .entry_points
+            .iter()
+            .position(|ep| ep.stage == naga::ShaderStage::Fragment)
+            .unwrap();
+        assert!(info.get_entry_point(fragment)[handle].contains(naga::valid::GlobalUse::READ));
+    }
+
+    #[test]
+    fn shader_time_wraps_hourly() {
+        assert_eq!(shader_time(Duration::ZERO), 0.0);
+        assert_eq!(shader_time(Duration::from_millis(1500)), 1.5);
+        assert_eq!(shader_time(Duration::from_secs(3600)), 0.0);
+        assert_eq!(shader_time(Duration::from_secs(3 * 3600 + 90)), 90.0);
+    }
+
+    #[test]
+    fn shader_time_keeps_precision_before_wrapping() {
+        let time = shader_time(Duration::from_secs_f64(TIME_WRAP - 0.001));
+        assert!((time as f64 - (TIME_WRAP - 0.001)).abs() < 0.00025);
+    }
+}
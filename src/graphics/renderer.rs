@@ -0,0 +1,400 @@
+// What gets drawn on the layer surfaces. The Wayland side (outputs, scaling, frame pacing, GPU
+// recovery) is the same for every wallpaper; a Renderer only fills the swapchain textures.
+use super::channels::{Animation, ChannelImage};
+use super::framework::WgpuConfig;
+use super::pipeline::{
+    shader_time, watch_shader, PassBuffers, PassSource, ShaderSource, ShaderWatcher, Uniforms,
+    WallpaperPipeline,
+};
+use crate::config::{ChannelConfig, ChannelFilter, ChannelWrap, Config, Scaling};
+use crate::error::LivelyError;
+use crate::{keyboard, trace};
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+use std::time::Instant;
+
+/// Everything about a frame that doesn't depend on the renderer.
+#[derive(Debug, Clone, Copy)]
+pub struct FrameContext {
+    /// Seconds since the wallpaper started, wrapped like the `time` uniform.
+    pub time: f32,
+    /// When the wallpaper started.
+    pub started: Instant,
+    /// Pointer position on the output in 0..1, from the top left.
+    pub mouse: [f32; 2],
+    /// Shadertoy's `iMouse`, in pixels from the bottom left: xy is the pointer position while the
+    /// left button is held, zw where it was pressed. z turns negative once the button is released
+    /// and w is only positive during the frame of the click.
+    pub imouse: [f32; 4],
+    /// Size of the swapchain texture in physical pixels.
+    pub resolution: [u32; 2],
+}
+
+/// Draws the wallpaper of one layer surface. Each surface gets its own renderer, made once the
+/// device is up and made again when the device or the swapchain format changes.
+pub trait Renderer: WgpuConfig {
+    fn init(device: &wgpu::Device, queue: &wgpu::Queue, format: wgpu::TextureFormat) -> Self;
+
+    /// Called with the swapchain size in physical pixels after `init` and whenever it changes.
+    fn resize(&mut self, width: u32, height: u32);
+
+    /// Draws a frame to `view` and submits it; the wallpaper presents it afterwards.
+    fn render(
+        &mut self,
+        view: &wgpu::TextureView,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        frame: &FrameContext,
+    );
+}
+
+/// The shader and its inputs, shared by the [`ShaderRenderer`]s of all surfaces.
+struct ShaderState {
+    shader: ShaderSource,
+    /// Watches the shader file for new versions, when one was given.
+    shader_watcher: Option<ShaderWatcher>,
+    /// Counts the shaders switched to, so every renderer picks up a switch or reload.
+    generation: u64,
+    /// Images for `iChannel0` to `iChannel3`; None for channels whose image didn't load.
+    channels: Vec<Option<ChannelImage>>,
+    /// Frames of an animated image in the image mode, shown as channel 0.
+    animation: Option<Animation>,
+    /// The render passes the pipeline is built with.
+    passes: Vec<PassSource>,
+    /// Audio capture feeding `audio_channel`.
+    #[cfg(feature = "audio")]
+    audio: Option<crate::audio::AudioCapture>,
+    #[cfg(feature = "audio")]
+    audio_channel: Option<usize>,
+    keyboard_channel: Option<usize>,
+    clear_color: wgpu::Color,
+}
+
+/// Set up by [`load_shaders`]; renderers made without it show the embedded shader.
+static SHADERS: Mutex<Option<ShaderState>> = Mutex::new(None);
+
+/// The shader state, with the embedded shader if none was loaded.
+fn shaders() -> MutexGuard<'static, Option<ShaderState>> {
+    let mut shaders = SHADERS.lock().unwrap();
+    shaders.get_or_insert_with(ShaderState::embedded);
+    shaders
+}
+
+/// Loads the shader, channels and passes of `config` for the [`ShaderRenderer`]s, and starts
+/// watching the shader file.
+pub(crate) fn load_shaders(config: &Config) -> Result<(), LivelyError> {
+    *SHADERS.lock().unwrap() = Some(ShaderState::load(config)?);
+    Ok(())
+}
+
+/// Switches the [`ShaderRenderer`]s to the shader at `path` and watches it for changes from then
+/// on, leaving the image mode if it was on. The current shader is kept if the new one doesn't
+/// load.
+pub(crate) fn switch_shader(path: &Path, shadertoy: bool) -> Result<(), String> {
+    let mut shaders = SHADERS.lock().unwrap();
+    let Some(state) = shaders.as_mut() else {
+        return Err("this wallpaper doesn't draw shaders".to_string());
+    };
+    let source = ShaderSource::load(path, shadertoy)?;
+    log::info!("Switching to {}", source.name);
+    // Replacing the watcher stops the previous one.
+    state.shader_watcher = Some(watch_shader(path.to_path_buf(), shadertoy));
+    state.shader = source;
+    state.animation = None;
+    // Every renderer rebuilds its pipeline from the new source on its next frame.
+    state.generation += 1;
+    Ok(())
+}
+
+/// Name of the shader shown, None when the wallpaper doesn't draw shaders.
+pub(crate) fn shader_name() -> Option<String> {
+    let shaders = SHADERS.lock().unwrap();
+    shaders.as_ref().map(|state| state.shader.name.clone())
+}
+
+/// Runs `f` on the animated image of the image mode, if one is shown.
+pub(crate) fn with_animation<T>(f: impl FnOnce(&mut Animation) -> T) -> Option<T> {
+    let mut shaders = SHADERS.lock().unwrap();
+    shaders.as_mut()?.animation.as_mut().map(f)
+}
+
+impl ShaderState {
+    fn embedded() -> Self {
+        Self {
+            shader: ShaderSource::embedded(),
+            shader_watcher: None,
+            generation: 0,
+            channels: Vec::new(),
+            animation: None,
+            passes: vec![PassSource::screen()],
+            #[cfg(feature = "audio")]
+            audio: None,
+            #[cfg(feature = "audio")]
+            audio_channel: None,
+            keyboard_channel: None,
+            clear_color: wgpu::Color::BLACK,
+        }
+    }
+
+    fn load(config: &Config) -> Result<Self, LivelyError> {
+        // The image mode draws its image as channel 0 with the built-in image shader.
+        let shader_path = config.shader.clone().filter(|_| config.image.is_none());
+        let shader = match (&config.image, &shader_path) {
+            (Some(_), _) => ShaderSource::image(config.scaling, config.clear_color),
+            (None, Some(path)) => {
+                ShaderSource::load(path, config.shadertoy).unwrap_or_else(|err| {
+                    eprintln!("{err}");
+                    log::warn!("Falling back to the embedded shader");
+                    ShaderSource::embedded()
+                })
+            }
+            (None, None) => ShaderSource::embedded(),
+        };
+        let mut animation = None;
+        let mut channels = match &config.image {
+            Some(path) => {
+                let wrap = if config.scaling == Scaling::Tile {
+                    ChannelWrap::Repeat
+                } else {
+                    ChannelWrap::Clamp
+                };
+                animation = Animation::load(path).map_err(LivelyError::Config)?;
+                let image = match &animation {
+                    Some(animation) => animation.first_frame(wrap),
+                    None => ChannelImage::load(&ChannelConfig {
+                        path: path.clone(),
+                        wrap,
+                        filter: ChannelFilter::Linear,
+                        vflip: false,
+                    })
+                    .map_err(LivelyError::Config)?,
+                };
+                vec![Some(image)]
+            }
+            None => config
+                .channels
+                .iter()
+                .map(|channel| {
+                    ChannelImage::load(channel)
+                        .map_err(|err| {
+                            eprintln!("{err}");
+                            log::warn!("Binding a black texture instead");
+                        })
+                        .ok()
+                })
+                .collect(),
+        };
+        #[cfg(feature = "audio")]
+        let audio = config.audio_channel.map(|channel| {
+            if channels.len() <= channel {
+                channels.resize_with(channel + 1, || None);
+            }
+            channels[channel] = Some(crate::audio::AudioCapture::image());
+            crate::audio::AudioCapture::start()
+        });
+        #[cfg(not(feature = "audio"))]
+        if config.audio_channel.is_some() {
+            log::warn!("audio_channel is ignored, lively-rs was built without the audio feature");
+        }
+        if let Some(channel) = config.keyboard_channel {
+            if channels.len() <= channel {
+                channels.resize_with(channel + 1, || None);
+            }
+            channels[channel] = Some(keyboard::image());
+        }
+        // Passes are for shaders; the image mode draws the image and nothing else.
+        let passes = if config.image.is_some() {
+            vec![PassSource::screen()]
+        } else {
+            PassSource::load_all(&config.passes).map_err(LivelyError::Config)?
+        };
+        let shader_watcher = shader_path.map(|path| watch_shader(path, config.shadertoy));
+        let [r, g, b, a] = config.clear_color;
+        Ok(Self {
+            shader,
+            shader_watcher,
+            generation: 0,
+            channels,
+            animation,
+            passes,
+            #[cfg(feature = "audio")]
+            audio,
+            #[cfg(feature = "audio")]
+            audio_channel: config.audio_channel,
+            keyboard_channel: config.keyboard_channel,
+            clear_color: wgpu::Color { r, g, b, a },
+        })
+    }
+}
+
+/// The default renderer, drawing the configured shader with its channels and passes.
+pub struct ShaderRenderer {
+    pipeline: WallpaperPipeline,
+    /// The [`ShaderState::generation`] `pipeline` was built from.
+    generation: u64,
+    size: (u32, u32),
+    /// Buffers of the passes, dropped with the pipeline.
+    buffers: Option<PassBuffers>,
+    /// The animation frame last uploaded to channel 0.
+    animation_frame: Option<usize>,
+}
+
+impl ShaderRenderer {
+    fn build(
+        shared: &mut ShaderState,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        format: wgpu::TextureFormat,
+    ) -> WallpaperPipeline {
+        WallpaperPipeline::new(
+            device,
+            queue,
+            format,
+            &shared.shader,
+            &shared.passes,
+            &shared.channels,
+        )
+        .unwrap_or_else(|err| {
+            eprintln!("{err}");
+            log::warn!("Falling back to the embedded shader");
+            shared.shader = ShaderSource::embedded();
+            shared.passes = vec![PassSource::screen()];
+            // Renderers built from the old shader switch to the embedded one too.
+            shared.generation += 1;
+            WallpaperPipeline::new(
+                device,
+                queue,
+                format,
+                &shared.shader,
+                &shared.passes,
+                &shared.channels,
+            )
+            .expect("the embedded shader is valid")
+        })
+    }
+}
+
+impl WgpuConfig for ShaderRenderer {}
+
+impl Renderer for ShaderRenderer {
+    fn init(device: &wgpu::Device, queue: &wgpu::Queue, format: wgpu::TextureFormat) -> Self {
+        let mut shaders = shaders();
+        let shared = shaders.as_mut().unwrap();
+        Self {
+            pipeline: Self::build(shared, device, queue, format),
+            generation: shared.generation,
+            size: (0, 0),
+            buffers: None,
+            animation_frame: None,
+        }
+    }
+
+    fn resize(&mut self, width: u32, height: u32) {
+        self.size = (width, height);
+    }
+
+    fn render(
+        &mut self,
+        view: &wgpu::TextureView,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        frame: &FrameContext,
+    ) {
+        let mut shaders = shaders();
+        let shared = shaders.as_mut().unwrap();
+        let format = self.pipeline.format;
+        // Only the latest saved version matters if several arrived since the last frame.
+        if let Some(source) = shared
+            .shader_watcher
+            .as_ref()
+            .and_then(|watcher| watcher.updates.try_iter().last())
+        {
+            match WallpaperPipeline::new(
+                device,
+                queue,
+                format,
+                &source,
+                &shared.passes,
+                &shared.channels,
+            ) {
+                Ok(pipeline) => {
+                    println!("Reloaded {}", source.name);
+                    shared.shader = source;
+                    shared.generation += 1;
+                    self.pipeline = pipeline;
+                    self.generation = shared.generation;
+                    self.buffers = None;
+                }
+                Err(err) => {
+                    eprintln!("{err}");
+                    log::warn!("Keeping the last working shader");
+                }
+            }
+        }
+        // The renderer of another surface picked up a new version.
+        if self.generation != shared.generation {
+            self.pipeline = Self::build(shared, device, queue, format);
+            self.generation = shared.generation;
+            self.buffers = None;
+        }
+        let pipeline = &self.pipeline;
+
+        if let Some(animation) = &shared.animation {
+            if self.animation_frame != Some(animation.frame()) {
+                let _span = trace::span("upload animation");
+                animation.upload(queue, &pipeline.channels.textures[0]);
+                self.animation_frame = Some(animation.frame());
+            }
+        }
+        #[cfg(feature = "audio")]
+        let loudness =
+            shared
+                .audio
+                .as_ref()
+                .zip(shared.audio_channel)
+                .map_or(0.0, |(audio, channel)| {
+                    let _span = trace::span("upload audio");
+                    audio.upload(queue, &pipeline.channels.textures[channel])
+                });
+        #[cfg(not(feature = "audio"))]
+        let loudness = 0.0;
+        let last_key = shared
+            .keyboard_channel
+            .and_then(|channel| {
+                let _span = trace::span("upload keyboard");
+                keyboard::upload(queue, &pipeline.channels.textures[channel])
+            })
+            .map_or(-1.0, |last_press| {
+                shader_time(last_press.saturating_duration_since(frame.started))
+            });
+        let [width, height] = frame.resolution;
+        let uniforms = Uniforms {
+            mouse: frame.mouse,
+            time: frame.time,
+            srgb: if format.describe().srgb { 1.0 } else { 0.0 },
+            resolution: [width as f32, height as f32],
+            _padding2: [0.0; 2],
+            imouse: frame.imouse,
+            loudness,
+            last_key,
+            _padding3: [0.0; 2],
+        };
+        {
+            let _span = trace::span("upload uniforms");
+            queue.write_buffer(&pipeline.uniform_buffer, 0, bytemuck::bytes_of(&uniforms));
+        }
+
+        // Buffers are sized like the swapchain, so a resize starts them over.
+        if self.buffers.as_ref().map(|buffers| buffers.size) != Some(self.size) {
+            self.buffers = Some(PassBuffers::new(device, pipeline.buffer_count, self.size));
+        }
+        let buffers = self.buffers.as_mut().unwrap();
+
+        let mut encoder = device.create_command_encoder(&Default::default());
+        pipeline.encode(device, &mut encoder, buffers, view, shared.clear_color);
+
+        // Submit the command in the queue to execute
+        let _span = trace::span("submit");
+        queue.submit(Some(encoder.finish()));
+    }
+}
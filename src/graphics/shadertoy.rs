@@ -0,0 +1,151 @@
+// Shadertoy compatibility: an image shader from Shadertoy (`mainImage`, `iTime`, ...) is wrapped
+// in a GLSL fragment shader that reads the wallpaper's uniforms, and translated to WGSL by naga.
+use super::glsl::{self, CHANNELS, UNIFORMS, VERSION};
+
+/// Shadertoy's inputs, defined in terms of the wallpaper's uniforms.
+const INPUTS: &str = "layout(location = 0) out vec4 lively_color;
+#define iTime uniforms.time
+#define iResolution vec3(uniforms.resolution, 1.0)
+#define iMouse uniforms.imouse
+";
+
+const FOOTER: &str = "
+void main() {
+    vec4 color = vec4(0.0, 0.0, 0.0, 1.0);
+    // Shadertoy's origin is the bottom left corner.
+    mainImage(color, vec2(gl_FragCoord.x, uniforms.resolution.y - gl_FragCoord.y));
+    // Like on Shadertoy, alpha is ignored.
+    lively_color = vec4(color.rgb, 1.0);
+}
+";
+
+/// Shadertoy inputs there is nothing to feed from, with what they're for.
+const UNSUPPORTED: [(&str, &str); 6] = [
+    ("iChannelResolution", "channel sizes"),
+    ("iChannelTime", "channel playback"),
+    ("iTimeDelta", "frame timing"),
+    ("iFrame", "frame counting"),
+    ("iFrameRate", "frame timing"),
+    ("iDate", "the date"),
+];
+
+/// Translates a Shadertoy image shader to a WGSL wallpaper shader with `vs_main` and `fs_main`.
+pub fn to_wgsl(name: &str, code: &str) -> Result<String, String> {
+    check_inputs(name, code)?;
+    let header = format!(
+        "{VERSION}{UNIFORMS}{CHANNELS}{}{INPUTS}",
+        glsl::prev_frame(code)
+    );
+    glsl::translate(name, &header, code, FOOTER)
+}
+
+/// Fails with everything the shader uses that can't be provided, rather than with whatever
+/// naga makes of the first undeclared identifier.
+fn check_inputs(name: &str, code: &str) -> Result<(), String> {
+    let code = strip_comments(code);
+    let identifiers: Vec<&str> = code
+        .split(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+        .collect();
+    if !identifiers.contains(&"mainImage") {
+        return Err(format!(
+            "{name}: no mainImage function, is this a Shadertoy shader?"
+        ));
+    }
+    let missing: Vec<String> = UNSUPPORTED
+        .iter()
+        .filter(|(input, _)| identifiers.contains(input))
+        .map(|(input, feature)| format!("{input} ({feature})"))
+        .collect();
+    if !missing.is_empty() {
+        return Err(format!(
+            "{name}: uses Shadertoy inputs lively-rs doesn't provide: {}",
+            missing.join(", ")
+        ));
+    }
+    Ok(())
+}
+
+/// Replaces comments with whitespace. GLSL comments don't nest, so a block comment ends at the
+/// first `*/`. Quoted text, which only appears in directives like `#error`, is kept as is.
+fn strip_comments(code: &str) -> String {
+    let mut stripped = String::with_capacity(code.len());
+    let mut rest = code;
+    while let Some(start) = rest.find(['/', '"']) {
+        stripped.push_str(&rest[..start]);
+        let after = &rest[start..];
+        if let Some(quoted) = after.strip_prefix('"') {
+            // Up to the closing quote, or the end of the line without one.
+            let end = quoted.find(['"', '\n']).map_or(quoted.len(), |end| end + 1);
+            stripped.push('"');
+            stripped.push_str(&quoted[..end]);
+            rest = &quoted[end..];
+        } else if after.starts_with("//") {
+            rest = after.find('\n').map_or("", |end| &after[end..]);
+        } else if after.starts_with("/*") {
+            rest = after.find("*/").map_or("", |end| &after[end + 2..]);
+            stripped.push(' ');
+        } else {
+            stripped.push('/');
+            rest = &after[1..];
+        }
+    }
+    stripped.push_str(rest);
+    stripped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_line_and_block_comments() {
+        assert_eq!(strip_comments("a // b\nc"), "a \nc");
+        assert_eq!(strip_comments("a/* b */c"), "a c");
+        assert_eq!(strip_comments("a / b"), "a / b");
+        // An unterminated comment runs to the end.
+        assert_eq!(strip_comments("a /* b"), "a  ");
+        assert_eq!(strip_comments("a // b"), "a ");
+    }
+
+    #[test]
+    fn block_comments_do_not_nest() {
+        assert_eq!(
+            strip_comments("/* outer /* inner */ mainImage */"),
+            "  mainImage */"
+        );
+    }
+
+    #[test]
+    fn keeps_comment_markers_inside_strings() {
+        assert_eq!(
+            strip_comments("#error \"see http://example.com/*\" // why\nx"),
+            "#error \"see http://example.com/*\" \nx"
+        );
+        // A quote left open ends at the end of the line.
+        assert_eq!(strip_comments("\"a // b\nc // d"), "\"a // b\nc ");
+    }
+
+    #[test]
+    fn requires_main_image() {
+        let err = check_inputs("a.glsl", "void main() {}").unwrap_err();
+        assert_eq!(
+            err,
+            "a.glsl: no mainImage function, is this a Shadertoy shader?"
+        );
+        // Mentioning it in a comment doesn't count.
+        assert!(check_inputs("a.glsl", "// mainImage\nvoid main() {}").is_err());
+        assert!(check_inputs("a.glsl", "void mainImage(out vec4 c, in vec2 p) {}").is_ok());
+    }
+
+    #[test]
+    fn reports_unsupported_inputs_outside_comments() {
+        let code = "void mainImage(out vec4 c, in vec2 p) { c = vec4(iTimeDelta, iFrame, 0, 1); }";
+        assert_eq!(
+            check_inputs("a.glsl", code).unwrap_err(),
+            "a.glsl: uses Shadertoy inputs lively-rs doesn't provide: \
+             iTimeDelta (frame timing), iFrame (frame counting)"
+        );
+        let code = "// iDate\n/* iFrame */ void mainImage(out vec4 c, in vec2 p) {}";
+        assert!(check_inputs("a.glsl", code).is_ok());
+    }
+}
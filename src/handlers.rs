@@ -0,0 +1,1236 @@
+// The Wayland event handlers of the wallpaper, and drawing a frame.
+use crate::graphics::framework::{Toplevel, Wallpaper};
+use crate::graphics::pipeline::shader_time;
+use crate::graphics::renderer::{FrameContext, Renderer};
+use crate::{error, graphics, keyboard, pacing, trace};
+use smithay_client_toolkit::{
+    compositor::CompositorHandler,
+    output::{OutputHandler, OutputState},
+    registry::{ProvidesRegistryState, RegistryState},
+    registry_handlers,
+    seat::{
+        keyboard::{KeyEvent, KeyboardHandler, Modifiers},
+        pointer::{PointerEvent, PointerEventKind, PointerHandler},
+        Capability, SeatHandler, SeatState,
+    },
+    shell::{
+        wlr_layer::{LayerShellHandler, LayerSurface, LayerSurfaceConfigure},
+        WaylandSurface,
+    },
+};
+use wayland_client::{
+    event_created_child,
+    protocol::{wl_keyboard, wl_output, wl_pointer, wl_seat, wl_surface},
+    Connection, Dispatch, Proxy, QueueHandle, WEnum,
+};
+use wayland_protocols::wp::fractional_scale::v1::client::{
+    wp_fractional_scale_manager_v1::{self, WpFractionalScaleManagerV1},
+    wp_fractional_scale_v1::{self, WpFractionalScaleV1},
+};
+use wayland_protocols::wp::viewporter::client::{
+    wp_viewport::{self, WpViewport},
+    wp_viewporter::{self, WpViewporter},
+};
+use wayland_protocols_wlr::foreign_toplevel::v1::client::{
+    zwlr_foreign_toplevel_handle_v1::{self, ZwlrForeignToplevelHandleV1},
+    zwlr_foreign_toplevel_manager_v1::{self, ZwlrForeignToplevelManagerV1},
+};
+use wayland_protocols_wlr::output_power_management::v1::client::{
+    zwlr_output_power_manager_v1::{self, ZwlrOutputPowerManagerV1},
+    zwlr_output_power_v1::{self, ZwlrOutputPowerV1},
+};
+
+use xkbcommon::xkb::keysyms;
+impl<R: Renderer> CompositorHandler for Wallpaper<R> {
+    fn scale_factor_changed(
+        &mut self,
+        _conn: &Connection,
+        qh: &QueueHandle<Self>,
+        surface: &wl_surface::WlSurface,
+        new_factor: i32,
+    ) {
+        let Some(index) = self.target_for_surface(surface) else {
+            return;
+        };
+        // wp-fractional-scale reports a more precise scale for the same surface.
+        if self.targets[index].fractional_scale.is_some() {
+            return;
+        }
+        let scale = new_factor.max(1);
+        surface.set_buffer_scale(scale);
+        self.set_scale(qh, index, scale as u32 * 120);
+    }
+
+    fn frame(
+        &mut self,
+        _conn: &Connection,
+        qh: &QueueHandle<Self>,
+        surface: &wl_surface::WlSurface,
+        _time: u32,
+    ) {
+        // println!("frame");
+        // Callbacks can still arrive for a layer surface that was torn down.
+        let Some(index) = self.target_for_surface(surface) else {
+            return;
+        };
+        let requested = self.targets[index].frame_requested.take();
+        // Don't request another frame while the output is off, covered or rendering is paused;
+        // resuming redraws right away.
+        if !self.should_render(index) {
+            log::debug!("Output is powered off, covered or paused, suspending rendering");
+            return;
+        }
+        if let Some(requested) = requested {
+            trace::record("callback wait", requested, std::time::Instant::now());
+        }
+        self.frame_due(qh, index);
+    }
+}
+
+impl<R: Renderer> OutputHandler for Wallpaper<R> {
+    fn output_state(&mut self) -> &mut OutputState {
+        &mut self.output_state
+    }
+
+    fn new_output(
+        &mut self,
+        _conn: &Connection,
+        qh: &QueueHandle<Self>,
+        _output: wl_output::WlOutput,
+    ) {
+        self.ensure_layers(qh);
+    }
+
+    fn update_output(
+        &mut self,
+        _conn: &Connection,
+        qh: &QueueHandle<Self>,
+        output: wl_output::WlOutput,
+    ) {
+        if self.pointer_output.as_ref() == Some(&output) {
+            self.update_pointer_scale();
+        }
+        // The name or description may only have become known now.
+        self.ensure_layers(qh);
+    }
+
+    fn output_destroyed(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        output: wl_output::WlOutput,
+    ) {
+        if let Some(index) = self.target_for_output(&output) {
+            self.destroy_layer(index);
+        }
+        if self.targets.is_empty() {
+            log::info!("Last output removed, waiting for one to appear");
+        }
+    }
+}
+
+/// What a draw does with the swapchain it has.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SwapchainAction {
+    /// It has the size of the last accepted configure.
+    Present,
+    /// It has another size and has to be configured to the accepted one first.
+    Reconfigure,
+}
+
+/// Decides whether a swapchain of size `swapchain` can be presented at the `accepted` buffer
+/// size.
+pub(crate) fn swapchain_action(swapchain: (u32, u32), accepted: (u32, u32)) -> SwapchainAction {
+    if swapchain == accepted {
+        SwapchainAction::Present
+    } else {
+        SwapchainAction::Reconfigure
+    }
+}
+
+/// Failed attempts at the first present before falling back to the next backend.
+const MAX_FAILED_PRESENTS: u32 = 3;
+
+/// Picks the surface format: an 8-bit sRGB one if `srgb` is set and there is one, otherwise the
+/// driver's first choice.
+fn choose_format(formats: &[wgpu::TextureFormat], srgb: bool) -> wgpu::TextureFormat {
+    let preferred = [
+        wgpu::TextureFormat::Bgra8UnormSrgb,
+        wgpu::TextureFormat::Rgba8UnormSrgb,
+    ];
+    srgb.then(|| {
+        preferred
+            .into_iter()
+            .find(|format| formats.contains(format))
+            .or_else(|| {
+                formats
+                    .iter()
+                    .copied()
+                    .find(|format| format.describe().srgb)
+            })
+    })
+    .flatten()
+    .unwrap_or(formats[0])
+}
+
+/// Configures smaller than this in either dimension are treated as transient and ignored.
+const MIN_SURFACE_SIZE: u32 = 16;
+
+/// What a layer configure asks of its target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ConfigureAction {
+    /// Too small to render to; keep the last accepted size until a sane one arrives.
+    Ignore,
+    /// The first usable size: bring up the GPU and start drawing.
+    Start,
+    /// The size we already have, nothing to reconfigure.
+    Unchanged,
+    /// A new size: reconfigure the swapchain and restart the frame loop if it was waiting.
+    Resize,
+}
+
+/// The size a configure gives the layer. An axis of 0 leaves it to us, so it takes the size
+/// we asked for in `[layer] size`.
+pub(crate) fn configured_size(new: (u32, u32), requested: [u32; 2]) -> (u32, u32) {
+    let pick = |configured: u32, requested: u32| {
+        if configured == 0 {
+            requested
+        } else {
+            configured
+        }
+    };
+    (pick(new.0, requested[0]), pick(new.1, requested[1]))
+}
+
+/// Decides what to do with a configure of size `new`, given the last accepted size, None before
+/// the first one.
+pub(crate) fn accept_configure(prev: Option<(u32, u32)>, new: (u32, u32)) -> ConfigureAction {
+    // Some compositors send transient configures with a zero or tiny size while outputs are
+    // reconfigured.
+    if new.0 < MIN_SURFACE_SIZE || new.1 < MIN_SURFACE_SIZE {
+        return ConfigureAction::Ignore;
+    }
+    match prev {
+        None => ConfigureAction::Start,
+        Some(prev) if prev == new => ConfigureAction::Unchanged,
+        Some(_) => ConfigureAction::Resize,
+    }
+}
+
+impl<R: Renderer> LayerShellHandler for Wallpaper<R> {
+    fn closed(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, layer: &LayerSurface) {
+        // The compositor closes a layer when its output goes away; keep the others running
+        // instead of exiting. Recreating it here would put it right back on the departing
+        // output, so that is left to new_output and update_output.
+        if let Some(index) = self.target_for_surface(layer.wl_surface()) {
+            self.destroy_layer(index);
+        }
+    }
+
+    fn configure(
+        &mut self,
+        _conn: &Connection,
+        qh: &QueueHandle<Self>,
+        layer: &LayerSurface,
+        configure: LayerSurfaceConfigure,
+        _serial: u32,
+    ) {
+        let _span = trace::span("configure");
+        let Some(index) = self.target_for_surface(layer.wl_surface()) else {
+            return;
+        };
+        log::trace!(
+            "configure {}x{} (serial {})",
+            configure.new_size.0,
+            configure.new_size.1,
+            _serial
+        );
+        let size = configured_size(configure.new_size, self.config.layer.size);
+        let target = &mut self.targets[index];
+        let (width, height) = size;
+        let prev = (!target.first_configure).then_some((target.width, target.height));
+        let action = accept_configure(prev, size);
+        if action == ConfigureAction::Ignore {
+            log::debug!(
+                "Ignoring degenerate configure {}x{}, keeping {}x{}",
+                width,
+                height,
+                target.width,
+                target.height
+            );
+            return;
+        }
+        target.width = width;
+        target.height = height;
+        // With fractional scaling the buffer is larger than the surface; map it back.
+        if let Some(viewport) = &target.viewport {
+            viewport.set_destination(width as i32, height as i32);
+        }
+
+        match action {
+            ConfigureAction::Ignore | ConfigureAction::Unchanged => {}
+            // Initiate the first draw.
+            ConfigureAction::Start => {
+                target.first_configure = false;
+                self.setup_gpu();
+                self.draw(qh, index);
+            }
+            ConfigureAction::Resize => {
+                if let Err(err) = self.configure_surface(index) {
+                    log::error!("{}", err);
+                }
+                if self.idle(index) {
+                    // Restart the frame loop; the draw configures the swapchain again if that
+                    // just failed.
+                    self.draw(qh, index);
+                }
+            }
+        }
+    }
+}
+
+impl<R: Renderer> SeatHandler for Wallpaper<R> {
+    fn seat_state(&mut self) -> &mut SeatState {
+        &mut self.seat_state
+    }
+
+    fn new_seat(&mut self, _: &Connection, _: &QueueHandle<Self>, _: wl_seat::WlSeat) {}
+
+    fn new_capability(
+        &mut self,
+        _conn: &Connection,
+        qh: &QueueHandle<Self>,
+        seat: wl_seat::WlSeat,
+        capability: Capability,
+    ) {
+        if capability == Capability::Keyboard && self.keyboard.is_none() {
+            println!("Set keyboard capability");
+            let keyboard = self
+                .seat_state
+                .get_keyboard(qh, &seat, None)
+                .expect("Failed to create keyboard");
+            self.keyboard = Some(keyboard);
+        }
+
+        if capability == Capability::Pointer && self.pointer.is_none() {
+            println!("Set pointer capability");
+            let pointer = self
+                .seat_state
+                .get_pointer(qh, &seat)
+                .expect("Failed to create pointer");
+            self.pointer = Some(pointer);
+        }
+    }
+
+    fn remove_capability(
+        &mut self,
+        _conn: &Connection,
+        _: &QueueHandle<Self>,
+        _: wl_seat::WlSeat,
+        capability: Capability,
+    ) {
+        if capability == Capability::Keyboard && self.keyboard.is_some() {
+            println!("Unset keyboard capability");
+            self.keyboard.take().unwrap().release();
+        }
+
+        if capability == Capability::Pointer && self.pointer.is_some() {
+            println!("Unset pointer capability");
+            self.pointer.take().unwrap().release();
+        }
+    }
+
+    fn remove_seat(&mut self, _: &Connection, _: &QueueHandle<Self>, _: wl_seat::WlSeat) {}
+}
+
+impl<R: Renderer> KeyboardHandler for Wallpaper<R> {
+    fn enter(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: &wl_keyboard::WlKeyboard,
+        surface: &wl_surface::WlSurface,
+        _: u32,
+        _: &[u32],
+        keysyms: &[u32],
+    ) {
+        if self.is_layer_surface(surface) {
+            println!("Keyboard focus on window with pressed syms: {keysyms:?}");
+            self.keyboard_focus = true;
+        }
+    }
+
+    fn leave(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: &wl_keyboard::WlKeyboard,
+        surface: &wl_surface::WlSurface,
+        _: u32,
+    ) {
+        if self.is_layer_surface(surface) {
+            println!("Release keyboard focus on window");
+            self.keyboard_focus = false;
+            // The releases of keys still held go to whoever has focus now.
+            keyboard::release_all();
+        }
+    }
+
+    fn press_key(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _: &wl_keyboard::WlKeyboard,
+        _: u32,
+        event: KeyEvent,
+    ) {
+        // Key events aren't printed, they would log what's typed with --keyboard-input.
+        if self.config.keyboard_channel.is_some() {
+            keyboard::set_key(event.raw_code, true);
+        }
+        // press 'esc' to exit
+        if event.keysym == keysyms::KEY_Escape {
+            self.exit = true;
+        }
+    }
+
+    fn release_key(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: &wl_keyboard::WlKeyboard,
+        _: u32,
+        event: KeyEvent,
+    ) {
+        if self.config.keyboard_channel.is_some() {
+            keyboard::set_key(event.raw_code, false);
+        }
+    }
+
+    fn update_modifiers(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: &wl_keyboard::WlKeyboard,
+        _serial: u32,
+        modifiers: Modifiers,
+    ) {
+        println!("Update modifiers: {modifiers:?}");
+    }
+}
+
+/// Linux input event code of the left mouse button.
+const BTN_LEFT: u32 = 0x110;
+
+impl<R: Renderer> PointerHandler for Wallpaper<R> {
+    fn pointer_frame(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _pointer: &wl_pointer::WlPointer,
+        events: &[PointerEvent],
+    ) {
+        use PointerEventKind::*;
+        for event in events {
+            // Ignore events for other surfaces
+            let Some(index) = self.target_for_surface(&event.surface) else {
+                continue;
+            };
+            println!("event");
+            match event.kind {
+                Enter { .. } => {
+                    println!("Pointer entered @{:?}", event.position);
+                    let output = self.targets[index].output.clone();
+                    // Surface-local, so offset by where this output sits in the layout.
+                    let ((x, y), _) = self.output_geometry(&output).unwrap_or_default();
+                    let mut pos = graphics::framework::POINTER_POS.lock().unwrap();
+                    *(pos) = (x + event.position.0, y + event.position.1);
+                    drop(pos);
+                    self.pointer_output = Some(output);
+                    self.update_pointer_scale();
+                }
+                Leave { .. } => {
+                    println!("Pointer left");
+                }
+                Motion { .. } => {
+                    println!("Pointer moving @{:?}", event.position);
+                    if self.mouse_down {
+                        let (x, y) = self.shadertoy_position(index, event.position);
+                        self.imouse[0] = x;
+                        self.imouse[1] = y;
+                    }
+                }
+                Press { button, .. } => {
+                    println!("Press {:x} @ {:?}", button, event.position);
+                    self.shift = self.shift.xor(Some(0));
+                    if button == BTN_LEFT {
+                        let (x, y) = self.shadertoy_position(index, event.position);
+                        self.mouse_down = true;
+                        self.imouse = [x, y, x, y];
+                    }
+                }
+                Release { button, .. } => {
+                    println!("Release {:x} @ {:?}", button, event.position);
+                    if button == BTN_LEFT {
+                        self.mouse_down = false;
+                        self.imouse[2] = -self.imouse[2].abs();
+                        self.imouse[3] = -self.imouse[3].abs();
+                    }
+                }
+                Axis {
+                    horizontal,
+                    vertical,
+                    ..
+                } => {
+                    println!("Scroll H:{horizontal:?}, V:{vertical:?}");
+                }
+            }
+        }
+    }
+}
+
+impl<R: Renderer> Wallpaper<R> {
+    /// Converts a surface-local position to Shadertoy's pixel coordinates, which are physical
+    /// and start at the bottom left.
+    fn shadertoy_position(&self, index: usize, (x, y): (f64, f64)) -> (f32, f32) {
+        let target = &self.targets[index];
+        let scale = target.scale_120 as f32 / 120.0;
+        (x as f32 * scale, (target.height as f32 - y as f32) * scale)
+    }
+
+    /// Whether a layer that should render has no frame callback or timer pending, so nothing
+    /// will draw it unless we do.
+    pub fn idle(&self, index: usize) -> bool {
+        let target = &self.targets[index];
+        !target.first_configure
+            && target.frame_requested.is_none()
+            && target.frame_timer.is_none()
+            && self.should_render(index)
+    }
+
+    /// Switches a layer to a new scale, in 120ths, resizing its swapchain to match.
+    fn set_scale(&mut self, qh: &QueueHandle<Self>, index: usize, scale_120: u32) {
+        let target = &mut self.targets[index];
+        if scale_120 == target.scale_120 {
+            return;
+        }
+        log::info!(
+            "Scale changed from {} to {}",
+            target.scale_120 as f32 / 120.0,
+            scale_120 as f32 / 120.0
+        );
+        target.scale_120 = scale_120;
+        if let Err(err) = self.configure_surface(index) {
+            log::error!("{}", err);
+        } else if self.idle(index) {
+            self.draw(qh, index);
+        }
+    }
+
+    /// (Re)configures the wgpu surface of a layer for its current size and scale. Reconfiguring
+    /// recreates the swapchain, so this is a no-op unless the size or format actually changed.
+    pub fn configure_surface(&mut self, index: usize) -> Result<(), String> {
+        let target = &self.targets[index];
+        let (Some(gpu), Some(surface)) = (&self.gpu, &target.surface) else {
+            return Ok(());
+        };
+        let swapchain_capabilities = surface.get_capabilities(&gpu.adapter);
+        let swapchain_format = choose_format(&swapchain_capabilities.formats, self.config.srgb);
+        let (width, height) = target.buffer_size();
+        if let Some(config) = &target.surface_config {
+            if (config.width, config.height, config.format) == (width, height, swapchain_format) {
+                return Ok(());
+            }
+        }
+        // Shader output is composited with what's behind the layer according to its alpha.
+        let alpha_mode = if swapchain_capabilities
+            .alpha_modes
+            .contains(&wgpu::CompositeAlphaMode::PreMultiplied)
+        {
+            wgpu::CompositeAlphaMode::PreMultiplied
+        } else {
+            wgpu::CompositeAlphaMode::Auto
+        };
+        let present_mode = self
+            .config
+            .present_mode
+            .choose(&swapchain_capabilities.present_modes);
+        if target.surface_config.is_none() {
+            log::info!(
+                "Using {:?} present mode (wanted {:?}, supported {:?})",
+                present_mode,
+                self.config.present_mode,
+                swapchain_capabilities.present_modes
+            );
+        }
+        log::debug!(
+            "Configuring surface {}x{} {:?}",
+            width,
+            height,
+            swapchain_format
+        );
+
+        let surface_config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format: swapchain_format,
+            view_formats: vec![swapchain_format],
+            alpha_mode,
+            width,
+            height,
+            present_mode,
+        };
+
+        gpu.device.push_error_scope(wgpu::ErrorFilter::Validation);
+        surface.configure(&gpu.device, &surface_config);
+        if let Some(err) = pollster::block_on(gpu.device.pop_error_scope()) {
+            return Err(format!("failed to configure surface: {err}"));
+        }
+        let id = self.targets[index].layer.wl_surface().id();
+        self.targets[index].surface_config = Some(surface_config);
+        if let Some((_, renderer)) = self.renderers.get_mut(&id) {
+            renderer.resize(width, height);
+        }
+        Ok(())
+    }
+
+    /// Asks for another frame callback without presenting, so a skipped frame doesn't stop the
+    /// animation.
+    fn skip_frame(&mut self, qh: &QueueHandle<Self>, index: usize) {
+        let target = &mut self.targets[index];
+        let wl_surface = target.layer.wl_surface();
+        wl_surface.frame(qh, wl_surface.clone());
+        wl_surface.commit();
+        target.frame_requested = Some(std::time::Instant::now());
+    }
+
+    pub fn draw(&mut self, _qh: &QueueHandle<Self>, index: usize) {
+        let _span = trace::span("draw");
+        let draw_started = std::time::Instant::now();
+        let target = &self.targets[index];
+        // Never present a buffer of a size other than the last accepted configure's; it would
+        // be stretched.
+        if let Some(config) = &target.surface_config {
+            let action = swapchain_action((config.width, config.height), target.buffer_size());
+            if action == SwapchainAction::Reconfigure {
+                if let Err(err) = self.configure_surface(index) {
+                    log::warn!("Deferring frame: {}", err);
+                    // Keep frame callbacks coming so the next frame tries again instead of the
+                    // layer freezing.
+                    self.skip_frame(_qh, index);
+                    return;
+                }
+            }
+        }
+        if self
+            .gpu
+            .as_ref()
+            .is_some_and(|gpu| gpu.lost.load(std::sync::atomic::Ordering::Relaxed))
+        {
+            self.reset_gpu();
+        }
+        let mut reconfigured = false;
+        let surface_texture = loop {
+            let target = &self.targets[index];
+            // No sane configure has arrived for this layer yet.
+            let (Some(surface), Some(_)) = (&target.surface, &target.surface_config) else {
+                return;
+            };
+            match surface.get_current_texture() {
+                Ok(texture) => break texture,
+                Err(wgpu::SurfaceError::OutOfMemory) => {
+                    self.fatal = Some(error::LivelyError::DeviceRequest(
+                        "out of memory while acquiring a swapchain texture".to_string(),
+                    ));
+                    self.exit = true;
+                    return;
+                }
+                // A backend can come up fine and still be unable to present (broken ICD, no
+                // Wayland WSI); move on to the next one rather than showing nothing.
+                Err(err) if self.first_present => {
+                    self.failed_presents += 1;
+                    log::warn!("Failed to acquire swapchain texture: {}", err);
+                    if self.failed_presents >= MAX_FAILED_PRESENTS {
+                        if !self.fall_back(error::LivelyError::DeviceRequest(format!(
+                            "failed to present: {err}"
+                        ))) {
+                            return;
+                        }
+                        self.setup_gpu();
+                    }
+                }
+                Err(wgpu::SurfaceError::Timeout) => {
+                    log::warn!("Timed out acquiring a swapchain texture, skipping a frame");
+                    self.skip_frame(_qh, index);
+                    return;
+                }
+                // Happens on some drivers after suspend/resume; the swapchain has to be made anew.
+                Err(err) => {
+                    if reconfigured {
+                        log::warn!("Swapchain still unusable after reconfiguring: {}", err);
+                        self.skip_frame(_qh, index);
+                        return;
+                    }
+                    log::info!("Reconfiguring swapchain: {}", err);
+                    reconfigured = true;
+                    self.targets[index].surface_config = None;
+                    // The surface failing to configure inside its error scope means the
+                    // device itself is gone, so it is made anew.
+                    if let Err(err) = self.configure_surface(index) {
+                        log::warn!("{}", err);
+                        self.reset_gpu();
+                    }
+                }
+            }
+        };
+        let Some(gpu) = &self.gpu else {
+            return;
+        };
+        let target = &self.targets[index];
+        let device = &gpu.device;
+        let queue = &gpu.queue;
+        let layer = &target.layer;
+        let wl_surface = layer.wl_surface();
+
+        let swapchain_format = target.surface_config.as_ref().unwrap().format;
+        let (buffer_width, buffer_height) = target.buffer_size();
+        let frame = FrameContext {
+            time: shader_time(self.started.elapsed()),
+            started: self.started,
+            mouse: self.normalized_mouse(target),
+            imouse: self.imouse,
+            resolution: [buffer_width, buffer_height],
+        };
+        // Like Shadertoy, w is only positive during the frame of the click.
+        self.imouse[3] = -self.imouse[3].abs();
+        let texture_view = surface_texture
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+
+        // Only make a new renderer when the swapchain format changes, not every frame.
+        let id = wl_surface.id();
+        if self.renderers.get(&id).map(|(format, _)| *format) != Some(swapchain_format) {
+            let mut renderer = R::init(device, queue, swapchain_format);
+            renderer.resize(buffer_width, buffer_height);
+            self.renderers
+                .insert(id.clone(), (swapchain_format, renderer));
+        }
+        let (_, renderer) = self.renderers.get_mut(&id).unwrap();
+        {
+            let _span = trace::span("render");
+            renderer.render(&texture_view, device, queue, &frame);
+        }
+        wl_surface.damage_buffer(0, 0, buffer_width as i32, buffer_height as i32);
+        // A still image is only drawn again when the surface changes.
+        let animated = self.config.image.is_none();
+        if animated {
+            wl_surface.frame(_qh, wl_surface.clone());
+        }
+        {
+            let _span = trace::span("present");
+            surface_texture.present();
+            layer.commit();
+            wl_surface.commit();
+        }
+        let interval = self.frame_interval();
+        let target = &mut self.targets[index];
+        target.frame_requested = animated.then(std::time::Instant::now);
+        target.next_frame = interval.map(|interval| match target.next_frame {
+            Some(deadline) => pacing::next_deadline(deadline, interval, draw_started),
+            None => draw_started + interval,
+        });
+        self.device_resets = 0;
+        if self.first_present {
+            self.first_present = false;
+            graphics::framework::log_startup_event("first frame presented");
+            self.backend_works();
+        }
+    }
+}
+
+impl<R: Renderer> Dispatch<ZwlrOutputPowerManagerV1, ()> for Wallpaper<R> {
+    fn event(
+        _: &mut Self,
+        _: &ZwlrOutputPowerManagerV1,
+        _: zwlr_output_power_manager_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl<R: Renderer> Dispatch<ZwlrOutputPowerV1, ()> for Wallpaper<R> {
+    fn event(
+        state: &mut Self,
+        power: &ZwlrOutputPowerV1,
+        event: zwlr_output_power_v1::Event,
+        _: &(),
+        _: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        let Some(index) = state
+            .targets
+            .iter()
+            .position(|target| target.output_power.as_ref() == Some(power))
+        else {
+            return;
+        };
+        let target = &mut state.targets[index];
+        match event {
+            zwlr_output_power_v1::Event::Mode { mode } => {
+                let powered = mode != WEnum::Value(zwlr_output_power_v1::Mode::Off);
+                if powered == target.output_powered {
+                    return;
+                }
+                log::info!("Output powered {}", if powered { "on" } else { "off" });
+                target.output_powered = powered;
+                state.update_mouse_suspension();
+                if !powered {
+                    return;
+                }
+                // Some drivers leave the swapchain unusable across a power cycle, so recreate it
+                // and push a fresh frame immediately so the output doesn't wake up to a stale or
+                // black one, unless a frame callback is still pending and will do so anyway.
+                state.targets[index].surface_config = None;
+                if let Err(err) = state.configure_surface(index) {
+                    log::error!("{}", err);
+                } else if state.idle(index) {
+                    state.draw(qh, index);
+                }
+            }
+            zwlr_output_power_v1::Event::Failed => {
+                // The output went away or another client holds its power control, so its power
+                // state can't be known from here on. Render as if it were on, as without the
+                // protocol: the compositor stops frame callbacks while it's off, which stops
+                // rendering to it all the same.
+                log::warn!("Output power state is unavailable, relying on frame callbacks");
+                target.output_power.take().unwrap().destroy();
+                if !target.output_powered {
+                    target.output_powered = true;
+                    state.update_mouse_suspension();
+                    if state.idle(index) {
+                        state.draw(qh, index);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl<R: Renderer> Wallpaper<R> {
+    /// Pauses layers whose output is covered by a fullscreen window, and resumes the others.
+    /// Only the focused window counts: a fullscreen window on a workspace that isn't shown
+    /// still reports itself as fullscreen.
+    fn update_occlusion(&mut self, qh: &QueueHandle<Self>) {
+        for index in 0..self.targets.len() {
+            let target = &self.targets[index];
+            let occluded = self.toplevels.iter().any(|toplevel| {
+                toplevel.fullscreen
+                    && toplevel.activated
+                    && !toplevel.minimized
+                    && toplevel.outputs.contains(&target.output)
+            });
+            if occluded == target.occluded {
+                continue;
+            }
+            log::info!(
+                "{} rendering on an output, fullscreen window {}",
+                if occluded { "Pausing" } else { "Resuming" },
+                if occluded { "opened" } else { "gone" }
+            );
+            self.targets[index].occluded = occluded;
+            // Push a fresh frame so the wallpaper doesn't show a stale one for a frame.
+            if self.idle(index) {
+                self.draw(qh, index);
+            }
+        }
+    }
+}
+
+impl<R: Renderer> Dispatch<ZwlrForeignToplevelManagerV1, ()> for Wallpaper<R> {
+    fn event(
+        state: &mut Self,
+        _: &ZwlrForeignToplevelManagerV1,
+        event: zwlr_foreign_toplevel_manager_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            zwlr_foreign_toplevel_manager_v1::Event::Toplevel { toplevel } => {
+                state.toplevels.push(Toplevel {
+                    handle: toplevel,
+                    outputs: Vec::new(),
+                    fullscreen: false,
+                    activated: false,
+                    minimized: false,
+                });
+            }
+            zwlr_foreign_toplevel_manager_v1::Event::Finished => {
+                state.toplevel_manager = None;
+            }
+            _ => {}
+        }
+    }
+
+    event_created_child!(Wallpaper<R>, ZwlrForeignToplevelManagerV1, [
+        zwlr_foreign_toplevel_manager_v1::EVT_TOPLEVEL_OPCODE => (ZwlrForeignToplevelHandleV1, ()),
+    ]);
+}
+
+impl<R: Renderer> Dispatch<ZwlrForeignToplevelHandleV1, ()> for Wallpaper<R> {
+    fn event(
+        state: &mut Self,
+        handle: &ZwlrForeignToplevelHandleV1,
+        event: zwlr_foreign_toplevel_handle_v1::Event,
+        _: &(),
+        _: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        use zwlr_foreign_toplevel_handle_v1::{Event, State};
+        let Some(index) = state
+            .toplevels
+            .iter()
+            .position(|toplevel| toplevel.handle == *handle)
+        else {
+            return;
+        };
+        let toplevel = &mut state.toplevels[index];
+        match event {
+            Event::OutputEnter { output } => toplevel.outputs.push(output),
+            Event::OutputLeave { output } => toplevel.outputs.retain(|o| *o != output),
+            Event::State { state: states } => {
+                let states: Vec<u32> = states
+                    .chunks_exact(4)
+                    .map(|bytes| u32::from_ne_bytes(bytes.try_into().unwrap()))
+                    .collect();
+                let has = |flag: State| states.contains(&(flag as u32));
+                toplevel.fullscreen = has(State::Fullscreen);
+                toplevel.activated = has(State::Activated);
+                toplevel.minimized = has(State::Minimized);
+            }
+            Event::Done => state.update_occlusion(qh),
+            Event::Closed => {
+                state.toplevels.remove(index).handle.destroy();
+                state.update_occlusion(qh);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl<R: Renderer> Dispatch<WpFractionalScaleManagerV1, ()> for Wallpaper<R> {
+    fn event(
+        _: &mut Self,
+        _: &WpFractionalScaleManagerV1,
+        _: wp_fractional_scale_manager_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl<R: Renderer> Dispatch<WpFractionalScaleV1, ()> for Wallpaper<R> {
+    fn event(
+        state: &mut Self,
+        fractional_scale: &WpFractionalScaleV1,
+        event: wp_fractional_scale_v1::Event,
+        _: &(),
+        _: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        let wp_fractional_scale_v1::Event::PreferredScale { scale } = event else {
+            return;
+        };
+        if let Some(index) = state
+            .targets
+            .iter()
+            .position(|target| target.fractional_scale.as_ref() == Some(fractional_scale))
+        {
+            state.set_scale(qh, index, scale);
+        }
+    }
+}
+
+impl<R: Renderer> Dispatch<WpViewporter, ()> for Wallpaper<R> {
+    fn event(
+        _: &mut Self,
+        _: &WpViewporter,
+        _: wp_viewporter::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl<R: Renderer> Dispatch<WpViewport, ()> for Wallpaper<R> {
+    fn event(
+        _: &mut Self,
+        _: &WpViewport,
+        _: wp_viewport::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl<R: Renderer> ProvidesRegistryState for Wallpaper<R> {
+    fn registry(&mut self) -> &mut RegistryState {
+        &mut self.registry_state
+    }
+    registry_handlers![OutputState, SeatState];
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ConfigureAction::*;
+    use super::*;
+
+    #[test]
+    fn first_usable_configure_starts() {
+        assert_eq!(accept_configure(None, (1920, 1080)), ConfigureAction::Start);
+        assert_eq!(
+            accept_configure(None, (MIN_SURFACE_SIZE, MIN_SURFACE_SIZE)),
+            ConfigureAction::Start
+        );
+    }
+
+    #[test]
+    fn zero_and_tiny_sizes_are_ignored() {
+        for size in [(0, 0), (0, 1080), (1920, 0), (1, 1), (15, 1080), (1920, 15)] {
+            assert_eq!(accept_configure(None, size), ConfigureAction::Ignore);
+            assert_eq!(
+                accept_configure(Some((1920, 1080)), size),
+                ConfigureAction::Ignore
+            );
+        }
+    }
+
+    /// Feeds configures through [`accept_configure`] the way the layer's configure handler
+    /// does, returning the actions and the size left accepted.
+    fn configure_sequence(sizes: &[(u32, u32)]) -> (Vec<ConfigureAction>, Option<(u32, u32)>) {
+        let mut accepted = None;
+        let actions = sizes
+            .iter()
+            .map(|&size| {
+                let action = accept_configure(accepted, size);
+                if action != ConfigureAction::Ignore {
+                    accepted = Some(size);
+                }
+                action
+            })
+            .collect();
+        (actions, accepted)
+    }
+
+    #[test]
+    fn degenerate_first_configures_wait_for_a_usable_one() {
+        let (actions, accepted) = configure_sequence(&[(0, 0), (1, 1), (1920, 1080)]);
+        assert_eq!(actions, [Ignore, Ignore, Start]);
+        assert_eq!(accepted, Some((1920, 1080)));
+    }
+
+    #[test]
+    fn transient_zero_configure_keeps_the_last_size() {
+        // The swapchain stays at the accepted size, so frames keep being presented through the
+        // 0x0 configure, and the same size coming back has nothing to reconfigure.
+        let (actions, accepted) = configure_sequence(&[(1920, 1080), (0, 0), (1920, 1080), (0, 0)]);
+        assert_eq!(actions, [Start, Ignore, Unchanged, Ignore]);
+        assert_eq!(accepted, Some((1920, 1080)));
+    }
+
+    #[test]
+    fn resize_after_transient_configure() {
+        let (actions, accepted) =
+            configure_sequence(&[(1920, 1080), (0, 0), (2560, 1440), (2560, 1440)]);
+        assert_eq!(actions, [Start, Ignore, Resize, Unchanged]);
+        assert_eq!(accepted, Some((2560, 1440)));
+    }
+
+    /// A layer driven the way the configure, frame and draw handlers drive it, with
+    /// configuring the swapchain succeeding or failing on demand.
+    #[derive(Default)]
+    struct Layer {
+        accepted: Option<(u32, u32)>,
+        swapchain: Option<(u32, u32)>,
+        frame_pending: bool,
+        presented: Vec<(u32, u32)>,
+    }
+
+    impl Layer {
+        fn configure(&mut self, size: (u32, u32), configures: bool) {
+            let action = accept_configure(self.accepted, size);
+            if action == Ignore {
+                return;
+            }
+            self.accepted = Some(size);
+            match action {
+                Ignore | Unchanged => {}
+                // setup_gpu falls back to the next backend rather than leave the layer without
+                // a swapchain.
+                Start => {
+                    self.swapchain = Some(size);
+                    self.draw(configures);
+                }
+                Resize => {
+                    if configures {
+                        self.swapchain = Some(size);
+                    }
+                    if !self.frame_pending {
+                        self.draw(configures);
+                    }
+                }
+            }
+        }
+
+        fn frame(&mut self, configures: bool) {
+            assert!(self.frame_pending, "frame callback nobody asked for");
+            self.frame_pending = false;
+            self.draw(configures);
+        }
+
+        fn draw(&mut self, configures: bool) {
+            let (Some(swapchain), Some(accepted)) = (self.swapchain, self.accepted) else {
+                return;
+            };
+            if swapchain_action(swapchain, accepted) == SwapchainAction::Reconfigure {
+                if !configures {
+                    // skip_frame
+                    self.frame_pending = true;
+                    return;
+                }
+                self.swapchain = Some(accepted);
+            }
+            self.presented.push(self.swapchain.unwrap());
+            self.frame_pending = true;
+        }
+    }
+
+    #[test]
+    fn frame_after_configure() {
+        let mut layer = Layer::default();
+        layer.configure((1920, 1080), true);
+        layer.frame(true);
+        layer.configure((2560, 1440), true);
+        layer.frame(true);
+        assert_eq!(layer.presented, [(1920, 1080), (1920, 1080), (2560, 1440)]);
+        assert!(layer.frame_pending);
+    }
+
+    #[test]
+    fn configure_after_frame() {
+        // The frame is drawn and presented first; the resize lands while its callback is
+        // pending, and the next frame is at the new size.
+        let mut layer = Layer::default();
+        layer.configure((1920, 1080), true);
+        layer.frame(true);
+        layer.configure((2560, 1440), true);
+        layer.configure((2560, 1440), true);
+        layer.frame(true);
+        assert_eq!(layer.presented, [(1920, 1080), (1920, 1080), (2560, 1440)]);
+    }
+
+    #[test]
+    fn failed_reconfigure_keeps_the_frame_loop_running() {
+        let mut layer = Layer::default();
+        layer.configure((1920, 1080), true);
+        // Configuring the swapchain fails on resize and on the next frames; nothing is
+        // presented at the old size, and frame callbacks keep coming until it works.
+        layer.configure((2560, 1440), false);
+        layer.frame(false);
+        assert!(layer.frame_pending);
+        layer.frame(false);
+        assert!(layer.frame_pending);
+        layer.frame(true);
+        assert_eq!(layer.presented, [(1920, 1080), (2560, 1440)]);
+        assert!(layer.frame_pending);
+    }
+
+    /// The layer-surface configure sizes in a WAYLAND_DEBUG=1 log.
+    fn recorded_configures(log: &str) -> Vec<(u32, u32)> {
+        log.lines()
+            .filter_map(|line| {
+                let args = line.split("zwlr_layer_surface_v1@").nth(1)?;
+                let args = args.split_once(".configure(")?.1.strip_suffix(')')?;
+                let mut args = args.split(", ").map(|arg| arg.parse().unwrap());
+                let _serial: u32 = args.next()?;
+                Some((args.next()?, args.next()?))
+            })
+            .collect()
+    }
+
+    /// Replays a recorded configure stream with a frame callback after each configure.
+    fn replay(log: &str) -> (Vec<ConfigureAction>, Layer) {
+        let mut layer = Layer::default();
+        let actions = recorded_configures(log)
+            .into_iter()
+            .map(|size| {
+                let size = configured_size(size, [0, 0]);
+                let action = accept_configure(layer.accepted, size);
+                layer.configure(size, true);
+                if layer.frame_pending {
+                    layer.frame(true);
+                }
+                action
+            })
+            .collect();
+        (actions, layer)
+    }
+
+    #[test]
+    fn recorded_hyprland_configures() {
+        let (actions, layer) = replay(include_str!("../tests/fixtures/configure-hyprland.log"));
+        assert_eq!(
+            actions,
+            [Start, Ignore, Ignore, Resize, Unchanged, Unchanged]
+        );
+        // Nothing is ever presented at the transient sizes.
+        assert!(layer
+            .presented
+            .iter()
+            .all(|&size| size == (2048, 1152) || size == (1920, 1080)));
+        assert_eq!(layer.accepted, Some((1920, 1080)));
+        assert!(layer.frame_pending);
+    }
+
+    #[test]
+    fn recorded_sway_configures() {
+        let (actions, layer) = replay(include_str!("../tests/fixtures/configure-sway.log"));
+        assert_eq!(actions, [Start, Resize, Resize, Unchanged]);
+        assert_eq!(layer.presented.last(), Some(&(1280, 720)));
+        assert!(layer.frame_pending);
+    }
+
+    #[test]
+    fn zero_axes_take_the_requested_size() {
+        assert_eq!(configured_size((1920, 1080), [0, 0]), (1920, 1080));
+        assert_eq!(configured_size((0, 0), [400, 300]), (400, 300));
+        assert_eq!(configured_size((1920, 0), [0, 48]), (1920, 48));
+        assert_eq!(configured_size((1920, 1080), [400, 300]), (1920, 1080));
+        // Nothing asked for on an axis the compositor leaves to us is still degenerate.
+        assert_eq!(
+            accept_configure(None, configured_size((0, 1080), [0, 0])),
+            ConfigureAction::Ignore
+        );
+    }
+
+    #[test]
+    fn repeated_sizes_are_unchanged() {
+        assert_eq!(
+            accept_configure(Some((1920, 1080)), (1920, 1080)),
+            ConfigureAction::Unchanged
+        );
+        assert_eq!(
+            accept_configure(Some((1920, 1080)), (2560, 1440)),
+            ConfigureAction::Resize
+        );
+        assert_eq!(
+            accept_configure(Some((1920, 1080)), (1080, 1920)),
+            ConfigureAction::Resize
+        );
+    }
+}
@@ -0,0 +1,70 @@
+// Built-in shader for --image: draws iChannel0 scaled to the surface. lively-rs prepends
+// `scaling()`, the scaling mode, and `background()`, the letterboxing color.
+struct Uniforms {
+    mouse: vec2<f32>,
+    time: f32,
+    srgb: f32,
+    resolution: vec2<f32>,
+    imouse: vec4<f32>,
+    loudness: f32,
+    last_key: f32,
+};
+
+@group(0) @binding(0)
+var<uniform> uniforms: Uniforms;
+
+@group(1) @binding(0)
+var image: texture_2d<f32>;
+@group(1) @binding(1)
+var image_sampler: sampler;
+
+@vertex
+fn vs_main(@builtin(vertex_index) in_vertex_index: u32) -> @builtin(position) vec4<f32> {
+    // A single triangle covering the whole surface.
+    let x = f32(i32(in_vertex_index & 1u) * 4 - 1);
+    let y = f32(i32(in_vertex_index >> 1u) * 4 - 1);
+    return vec4<f32>(x, y, 0.0, 1.0);
+}
+
+fn srgb_to_linear(color: vec3<f32>) -> vec3<f32> {
+    let low = color / 12.92;
+    let high = pow((color + 0.055) / 1.055, vec3<f32>(2.4));
+    return select(high, low, color <= vec3<f32>(0.04045));
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let surface = uniforms.resolution;
+    let size = vec2<f32>(textureDimensions(image));
+    // Surface pixels per image pixel on each axis.
+    var scale = surface / size;
+    switch scaling() {
+        // fill
+        case 0u: {
+            scale = vec2<f32>(max(scale.x, scale.y));
+        }
+        // fit
+        case 1u: {
+            scale = vec2<f32>(min(scale.x, scale.y));
+        }
+        // center, tile
+        case 2u, 4u: {
+            scale = vec2<f32>(1.0);
+        }
+        // stretch
+        default: {}
+    }
+    let scaled = size * scale;
+    var uv = (position.xy - (surface - scaled) * 0.5) / scaled;
+    if scaling() == 4u {
+        uv = position.xy / size;
+    }
+    // Sampled outside of any branch, mipmapping needs derivatives.
+    var color = textureSample(image, image_sampler, uv);
+    // The image holds sRGB values; an sRGB surface expects linear ones.
+    if uniforms.srgb > 0.5 {
+        color = vec4<f32>(srgb_to_linear(color.rgb), color.a);
+    }
+    let inside = scaling() == 4u || (all(uv >= vec2<f32>(0.0)) && all(uv <= vec2<f32>(1.0)));
+    return select(background(), color, inside);
+}
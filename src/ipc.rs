@@ -0,0 +1,249 @@
+// Control socket at $XDG_RUNTIME_DIR/lively-rs.sock. Clients send one JSON command per line,
+// e.g. {"command": "set-fps", "fps": 30}, and get one JSON reply per line back.
+use crate::config::BatteryPolicy;
+use crate::graphics::framework::Wallpaper;
+use crate::graphics::renderer::{self, Renderer};
+use crate::trace;
+use calloop::{generic::Generic, Interest, LoopHandle, Mode, PostAction};
+use serde::{Deserialize, Serialize};
+use std::io::{self, Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use wayland_client::QueueHandle;
+
+/// Lines longer than this are not commands; the client is disconnected.
+const MAX_LINE: usize = 64 * 1024;
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "command", rename_all = "kebab-case")]
+enum Command {
+    SetShader { path: PathBuf },
+    Pause,
+    Resume,
+    SetFps { fps: u32 },
+    SetBatteryPolicy { policy: BatteryPolicy },
+    Status,
+    Quit,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct Reply {
+    ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<Status>,
+}
+
+#[derive(Debug, Serialize)]
+struct Status {
+    /// None when the wallpaper doesn't draw shaders.
+    shader: Option<String>,
+    fps: u32,
+    paused: bool,
+    on_battery: bool,
+    outputs: Vec<OutputStatus>,
+    adapter: Option<String>,
+    /// Frame timings from the trace spans, only recorded with trace logging or
+    /// `--tracing-chrome`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    timings: Vec<Timing>,
+}
+
+#[derive(Debug, Serialize)]
+struct Timing {
+    span: String,
+    count: u64,
+    mean_ms: f64,
+    max_ms: f64,
+}
+
+#[derive(Debug, Serialize)]
+struct OutputStatus {
+    name: Option<String>,
+    /// False while the output is off, only known with `watch_output_power`.
+    output_powered: bool,
+    /// Whether a fullscreen window covers the output.
+    occluded: bool,
+}
+
+pub fn socket_path() -> Option<PathBuf> {
+    std::env::var_os("XDG_RUNTIME_DIR").map(|dir| PathBuf::from(dir).join("lively-rs.sock"))
+}
+
+/// The control socket this process bound, removed when dropped. Only `listen` makes one, so
+/// an instance that found the socket taken never removes another instance's socket.
+pub struct SocketGuard {
+    path: PathBuf,
+}
+
+impl Drop for SocketGuard {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Starts accepting connections on the control socket from the event loop. A socket left
+/// behind by a crashed instance is replaced, one that still answers is left alone.
+pub fn listen<R: Renderer>(
+    handle: &LoopHandle<'static, Wallpaper<R>>,
+    qh: &QueueHandle<Wallpaper<R>>,
+) -> Result<SocketGuard, String> {
+    let path = socket_path().ok_or("XDG_RUNTIME_DIR is not set")?;
+    if UnixStream::connect(&path).is_ok() {
+        return Err(format!(
+            "another instance is listening on {}",
+            path.display()
+        ));
+    }
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path)
+        .map_err(|err| format!("failed to bind {}: {}", path.display(), err))?;
+    let guard = SocketGuard { path: path.clone() };
+    listener
+        .set_nonblocking(true)
+        .map_err(|err| format!("failed to bind {}: {}", path.display(), err))?;
+    let qh = qh.clone();
+    let loop_handle = handle.clone();
+    handle
+        .insert_source(
+            Generic::new(listener, Interest::READ, Mode::Level),
+            move |_, listener, _| {
+                loop {
+                    match listener.accept() {
+                        Ok((stream, _)) => accept(&loop_handle, &qh, stream),
+                        Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                        Err(err) => log::warn!("Failed to accept control connection: {}", err),
+                    }
+                }
+                Ok(PostAction::Continue)
+            },
+        )
+        .map_err(|err| format!("failed to listen on {}: {}", path.display(), err))?;
+    log::info!("Listening for commands on {}", path.display());
+    Ok(guard)
+}
+
+fn accept<R: Renderer>(
+    handle: &LoopHandle<'static, Wallpaper<R>>,
+    qh: &QueueHandle<Wallpaper<R>>,
+    stream: UnixStream,
+) {
+    if let Err(err) = stream.set_nonblocking(true) {
+        log::warn!("Failed to set up control connection: {}", err);
+        return;
+    }
+    let qh = qh.clone();
+    let mut buffer = Vec::new();
+    let inserted = handle.insert_source(
+        Generic::new(stream, Interest::READ, Mode::Level),
+        move |_, stream, w| {
+            let mut chunk = [0; 4096];
+            loop {
+                match stream.read(&mut chunk) {
+                    Ok(0) => return Ok(PostAction::Remove),
+                    Ok(read) => buffer.extend_from_slice(&chunk[..read]),
+                    Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                    Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(_) => return Ok(PostAction::Remove),
+                }
+            }
+            while let Some(end) = buffer.iter().position(|&byte| byte == b'\n') {
+                let line: Vec<u8> = buffer.drain(..=end).collect();
+                let line = String::from_utf8_lossy(&line);
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let reply = match serde_json::from_str::<Command>(&line) {
+                    Ok(command) => run(w, &qh, command),
+                    Err(err) => failure(format!("invalid command: {err}")),
+                };
+                let mut reply = serde_json::to_string(&reply).expect("replies serialize");
+                reply.push('\n');
+                // Replies are small enough to fit in the socket buffer of a client that reads them.
+                if stream.write_all(reply.as_bytes()).is_err() {
+                    return Ok(PostAction::Remove);
+                }
+            }
+            if buffer.len() > MAX_LINE {
+                return Ok(PostAction::Remove);
+            }
+            Ok(PostAction::Continue)
+        },
+    );
+    if let Err(err) = inserted {
+        log::warn!("Failed to set up control connection: {}", err);
+    }
+}
+
+fn failure(error: String) -> Reply {
+    Reply {
+        error: Some(error),
+        ..Reply::default()
+    }
+}
+
+fn run<R: Renderer>(
+    w: &mut Wallpaper<R>,
+    qh: &QueueHandle<Wallpaper<R>>,
+    command: Command,
+) -> Reply {
+    log::info!("Control command: {:?}", command);
+    match command {
+        Command::SetShader { path } => {
+            if let Err(err) = w.set_shader(qh, path) {
+                return failure(err);
+            }
+        }
+        Command::Pause => w.set_paused(qh, true),
+        Command::Resume => w.set_paused(qh, false),
+        Command::SetFps { fps } => w.config.fps = fps,
+        Command::SetBatteryPolicy { policy } => {
+            w.config.battery.policy = policy;
+            w.resume_idle(qh);
+        }
+        Command::Status => {
+            return Reply {
+                ok: true,
+                status: Some(status(w)),
+                ..Reply::default()
+            }
+        }
+        Command::Quit => w.exit = true,
+    }
+    Reply {
+        ok: true,
+        ..Reply::default()
+    }
+}
+
+fn status<R: Renderer>(w: &Wallpaper<R>) -> Status {
+    Status {
+        shader: renderer::shader_name(),
+        fps: w.config.fps,
+        paused: w.paused(),
+        on_battery: w.on_battery,
+        outputs: w
+            .targets
+            .iter()
+            .map(|target| OutputStatus {
+                name: w
+                    .output_state
+                    .info(&target.output)
+                    .and_then(|info| info.name),
+                output_powered: target.output_powered,
+                occluded: target.occluded,
+            })
+            .collect(),
+        adapter: w.gpu.as_ref().map(|gpu| gpu.adapter.get_info().name),
+        timings: trace::stats()
+            .into_iter()
+            .map(|(span, stats)| Timing {
+                span,
+                count: stats.count,
+                mean_ms: stats.mean().as_secs_f64() * 1000.0,
+                max_ms: stats.max.as_secs_f64() * 1000.0,
+            })
+            .collect(),
+    }
+}
@@ -0,0 +1,71 @@
+// Key states for typing-reactive shaders, only with --keyboard-input. Shaders see which keys are
+// held down and when a key was last pressed, never which keys were typed in what order.
+use crate::config::ChannelFilter;
+use crate::graphics::channels::ChannelImage;
+use std::num::NonZeroU32;
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// Width of the key texture; keys are indexed by evdev key code, higher codes are ignored.
+pub const KEY_COUNT: usize = 256;
+
+struct KeyStates {
+    down: [bool; KEY_COUNT],
+    last_press: Option<Instant>,
+}
+
+/// Fed by the keyboard focus of the layers and by the libinput tracker, whichever sees the key.
+static KEYS: Mutex<KeyStates> = Mutex::new(KeyStates {
+    down: [false; KEY_COUNT],
+    last_press: None,
+});
+
+/// Records a key going down or up, by evdev key code.
+pub fn set_key(code: u32, pressed: bool) {
+    let mut keys = KEYS.lock().unwrap();
+    if let Some(down) = keys.down.get_mut(code as usize) {
+        *down = pressed;
+    }
+    if pressed {
+        keys.last_press = Some(Instant::now());
+    }
+}
+
+/// Lets go of all keys, for when their releases won't arrive, e.g. after losing focus.
+pub fn release_all() {
+    KEYS.lock().unwrap().down = [false; KEY_COUNT];
+}
+
+/// The texture the key states are uploaded to, with every key up.
+pub fn image() -> ChannelImage {
+    ChannelImage::blank(KEY_COUNT as u32, 1, ChannelFilter::Nearest)
+}
+
+/// Uploads the key states to `texture`, made from [`image`], as white for keys held down, and
+/// returns when a key was last pressed.
+pub fn upload(queue: &wgpu::Queue, texture: &wgpu::Texture) -> Option<Instant> {
+    let keys = KEYS.lock().unwrap();
+    let pixels: Vec<u8> = keys
+        .down
+        .iter()
+        .flat_map(|&down| {
+            let value = if down { 255 } else { 0 };
+            [value, value, value, 255]
+        })
+        .collect();
+    queue.write_texture(
+        texture.as_image_copy(),
+        &pixels,
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: NonZeroU32::new(4 * KEY_COUNT as u32),
+            rows_per_image: None,
+        },
+        wgpu::Extent3d {
+            width: KEY_COUNT as u32,
+            height: 1,
+            depth_or_array_layers: 1,
+        },
+    );
+    keys.last_press
+}
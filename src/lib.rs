@@ -0,0 +1,62 @@
+// Animated shader wallpapers for wlroots compositors, as a library: build [`Settings`] and hand
+// them to [`run`], or to [`run_with`] along with a [`Renderer`] of your own. The lively-rs binary
+// in src/bin is a command line front end for it.
+#[cfg(feature = "audio")]
+mod audio;
+pub mod config;
+pub mod error;
+mod graphics;
+mod handlers;
+mod ipc;
+mod keyboard;
+mod pacing;
+mod power;
+mod trace;
+
+pub use config::Config;
+pub use error::LivelyError;
+pub use graphics::framework::WgpuConfig;
+pub use graphics::renderer::{FrameContext, Renderer, ShaderRenderer};
+
+use std::path::PathBuf;
+
+/// Everything a wallpaper is started with.
+#[derive(Debug, Default)]
+pub struct Settings {
+    pub config: Config,
+    /// Print the outputs the compositor announces instead of showing a wallpaper.
+    pub list_outputs: bool,
+    /// Write frame timing spans to this file as a Chrome trace on exit.
+    pub tracing_chrome: Option<PathBuf>,
+}
+
+/// Starts the clock of the startup timeline logged at info level. [`run`] starts it otherwise,
+/// missing whatever came before, e.g. loading the config.
+pub fn mark_startup() {
+    graphics::framework::STARTUP.get_or_init(std::time::Instant::now);
+}
+
+/// Shows the configured shader until it is told to quit, or fails.
+pub fn run(settings: Settings) -> Result<(), LivelyError> {
+    start::<ShaderRenderer>(settings, graphics::renderer::load_shaders)
+}
+
+/// Shows a wallpaper drawn by `R` until it is told to quit, or fails. The shader settings of the
+/// config are left to [`run`].
+pub fn run_with<R: Renderer>(settings: Settings) -> Result<(), LivelyError> {
+    start::<R>(settings, |_| Ok(()))
+}
+
+fn start<R: Renderer>(
+    settings: Settings,
+    prepare: impl FnOnce(&Config) -> Result<(), LivelyError>,
+) -> Result<(), LivelyError> {
+    mark_startup();
+    if settings.list_outputs {
+        return graphics::framework::list_outputs();
+    }
+    if let Some(path) = &settings.tracing_chrome {
+        trace::enable_chrome(path);
+    }
+    pollster::block_on(graphics::framework::setup::<R>(settings.config, prepare))
+}
@@ -0,0 +1,104 @@
+// Pacing for timed loops. `thread::sleep` oversleeps by the timer slack on every iteration, which
+// adds up and makes a loop run measurably slower than asked; `Pacer` instead sleeps until absolute
+// deadlines on a timerfd, each one exactly one interval after the previous.
+use crate::error::LivelyError;
+use nix::sys::time::TimeSpec;
+use nix::sys::timerfd::{ClockId, Expiration, TimerFd, TimerFlags, TimerSetTimeFlags};
+use nix::time::{clock_gettime, ClockId as TimeClockId};
+use std::ops::Add;
+use std::time::Duration;
+
+pub struct Pacer {
+    interval: Duration,
+    /// Absolute `CLOCK_MONOTONIC` time of the next tick.
+    deadline: Duration,
+    timer: TimerFd,
+}
+
+impl Pacer {
+    pub fn new(interval: Duration) -> Result<Self, LivelyError> {
+        let timer = TimerFd::new(ClockId::CLOCK_MONOTONIC, TimerFlags::TFD_CLOEXEC)
+            .map_err(|err| LivelyError::EventLoop(format!("failed to create timerfd: {err}")))?;
+        Ok(Self {
+            interval,
+            deadline: monotonic_now() + interval,
+            timer,
+        })
+    }
+
+    /// Blocks until the next tick.
+    pub fn wait(&mut self) {
+        if self.deadline > monotonic_now() {
+            let armed = self.timer.set(
+                Expiration::OneShot(TimeSpec::from(self.deadline)),
+                TimerSetTimeFlags::TFD_TIMER_ABSTIME,
+            );
+            if let Err(err) = armed.and_then(|()| self.timer.wait()) {
+                log::warn!("timerfd wait failed: {}", err);
+                std::thread::sleep(self.deadline.saturating_sub(monotonic_now()));
+            }
+        }
+
+        self.deadline = next_deadline(self.deadline, self.interval, monotonic_now());
+    }
+}
+
+/// The deadline after `deadline`, one `interval` later. Stepping from the previous deadline
+/// rather than from now means lateness in one iteration is made up for in the next instead of
+/// accumulating.
+pub fn next_deadline<T>(deadline: T, interval: Duration, now: T) -> T
+where
+    T: Add<Duration, Output = T> + Ord + Copy,
+{
+    let next = deadline + interval;
+    if next < now {
+        // We fell more than a whole interval behind (suspend, a stalled iteration); skip the
+        // missed ticks instead of running them back to back.
+        now + interval
+    } else {
+        next
+    }
+}
+
+fn monotonic_now() -> Duration {
+    clock_gettime(TimeClockId::CLOCK_MONOTONIC)
+        .map(Duration::from)
+        .expect("CLOCK_MONOTONIC is always available")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    #[test]
+    fn deadlines_step_from_the_previous_one() {
+        let interval = Duration::from_millis(10);
+        let start = Duration::from_millis(100);
+        // A late wakeup doesn't push the next deadline back.
+        assert_eq!(
+            next_deadline(start, interval, start + Duration::from_millis(3)),
+            Duration::from_millis(110)
+        );
+        // Falling more than an interval behind skips the missed ticks.
+        assert_eq!(
+            next_deadline(start, interval, Duration::from_millis(125)),
+            Duration::from_millis(135)
+        );
+    }
+
+    #[test]
+    fn paces_60_hz() {
+        const TICKS: u32 = 120;
+        let mut pacer = Pacer::new(Duration::from_secs(1) / 60).unwrap();
+        // Measure from a tick rather than from creation, so both ends have the same wakeup
+        // latency.
+        pacer.wait();
+        let start = Instant::now();
+        for _ in 0..TICKS {
+            pacer.wait();
+        }
+        let rate = TICKS as f64 / start.elapsed().as_secs_f64();
+        assert!((rate - 60.0).abs() < 0.1, "paced at {rate:.3} Hz");
+    }
+}
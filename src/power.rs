@@ -0,0 +1,40 @@
+// Power source detection from /sys/class/power_supply, for the battery policy.
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+/// How often the power supplies are checked; plugging in or out is noticed within this.
+pub const POWER_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+const POWER_SUPPLY_DIR: &str = "/sys/class/power_supply";
+
+/// Whether the system is running on battery: it has a system battery and no external supply
+/// (AC adapter, USB-C charger) is online. Desktops without batteries are never on battery.
+pub fn on_battery() -> bool {
+    let Ok(entries) = fs::read_dir(POWER_SUPPLY_DIR) else {
+        return false;
+    };
+    let mut has_battery = false;
+    for entry in entries.flatten() {
+        let supply = entry.path();
+        match read(&supply, "type").as_deref() {
+            // Batteries of wireless mice and the like have the "Device" scope.
+            Some("Battery") if read(&supply, "scope").as_deref() != Some("Device") => {
+                has_battery = true;
+            }
+            Some("Battery") | None => {}
+            Some(_) => {
+                if read(&supply, "online").as_deref() == Some("1") {
+                    return false;
+                }
+            }
+        }
+    }
+    has_battery
+}
+
+fn read(supply: &Path, attribute: &str) -> Option<String> {
+    fs::read_to_string(supply.join(attribute))
+        .ok()
+        .map(|value| value.trim().to_string())
+}
@@ -1,3 +1,17 @@
+// Bound by lively-rs at @group(0) @binding(0), see `Uniforms` in src/graphics/pipeline.rs.
+struct Uniforms {
+    mouse: vec2<f32>,
+    time: f32,
+    srgb: f32,
+    resolution: vec2<f32>,
+    imouse: vec4<f32>,
+    loudness: f32,
+    last_key: f32,
+};
+
+@group(0) @binding(0)
+var<uniform> uniforms: Uniforms;
+
 @vertex
 fn vs_main(@builtin(vertex_index) in_vertex_index: u32) -> @builtin(position) vec4<f32> {
     let x = f32(i32(in_vertex_index) - 1);
@@ -7,6 +21,5 @@ fn vs_main(@builtin(vertex_index) in_vertex_index: u32) -> @builtin(position) ve
 
 @fragment
 fn fs_main() -> @location(0) vec4<f32> {
-    return vec4<f32>(1.0, 0.0, 0.0, 1.0);
+    return vec4<f32>(0.5 + 0.5 * sin(uniforms.time), 0.0, 0.0, 1.0);
 }
-
@@ -0,0 +1,227 @@
+// Spans around the frame lifecycle. They are logged at trace level, summed up into per-span
+// timing statistics, and can additionally be collected into a Chrome trace (chrome://tracing,
+// Perfetto) with `--tracing-chrome <file>`.
+use serde::Serialize;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, VecDeque};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// Spans kept for the Chrome trace; older ones are dropped so a long-running daemon doesn't
+/// grow without bound. At a few spans per frame this is several minutes at 60 fps.
+const MAX_EVENTS: usize = 100_000;
+
+static CHROME_ENABLED: AtomicBool = AtomicBool::new(false);
+static CHROME_PATH: OnceLock<PathBuf> = OnceLock::new();
+static EVENTS: Mutex<VecDeque<Event>> = Mutex::new(VecDeque::new());
+static STATS: Mutex<BTreeMap<Cow<'static, str>, Stats>> = Mutex::new(BTreeMap::new());
+static EPOCH: OnceLock<Instant> = OnceLock::new();
+
+struct Event {
+    name: Cow<'static, str>,
+    start_us: u128,
+    duration_us: u128,
+}
+
+/// Timings of all spans with the same name.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub count: u64,
+    pub total: Duration,
+    pub max: Duration,
+}
+
+impl Stats {
+    fn add(&mut self, duration: Duration) {
+        self.count += 1;
+        self.total += duration;
+        self.max = self.max.max(duration);
+    }
+
+    pub fn mean(&self) -> Duration {
+        if self.count == 0 {
+            Duration::ZERO
+        } else {
+            self.total / self.count as u32
+        }
+    }
+}
+
+/// A timed region of the frame path, recorded when dropped.
+pub struct Span {
+    name: Cow<'static, str>,
+    start: Option<Instant>,
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        if let Some(start) = self.start {
+            record(std::mem::take(&mut self.name), start, Instant::now());
+        }
+    }
+}
+
+fn enabled() -> bool {
+    CHROME_ENABLED.load(Ordering::Relaxed) || log::log_enabled!(log::Level::Trace)
+}
+
+/// Starts a span that lasts until the returned guard is dropped.
+/// When neither trace logging nor the Chrome trace is enabled this does not even read the clock.
+pub fn span(name: &'static str) -> Span {
+    let start = if enabled() {
+        Some(Instant::now())
+    } else {
+        None
+    };
+    Span {
+        name: Cow::Borrowed(name),
+        start,
+    }
+}
+
+/// Like [`span`], for names made at runtime; `name` is only called when spans are recorded.
+pub fn span_with(name: impl FnOnce() -> String) -> Span {
+    if enabled() {
+        Span {
+            name: Cow::Owned(name()),
+            start: Some(Instant::now()),
+        }
+    } else {
+        Span {
+            name: Cow::Borrowed(""),
+            start: None,
+        }
+    }
+}
+
+/// Records a span whose start and end were measured by the caller, e.g. the wait between
+/// requesting a frame callback and receiving it.
+pub fn record(name: impl Into<Cow<'static, str>>, start: Instant, end: Instant) {
+    if !enabled() {
+        return;
+    }
+    let name = name.into();
+    let duration = end.saturating_duration_since(start);
+    log::trace!("{} took {:.3}ms", name, duration.as_secs_f64() * 1000.0);
+    STATS
+        .lock()
+        .unwrap()
+        .entry(name.clone())
+        .or_default()
+        .add(duration);
+    if CHROME_ENABLED.load(Ordering::Relaxed) {
+        let epoch = EPOCH.get_or_init(Instant::now);
+        let mut events = EVENTS.lock().unwrap();
+        if events.len() == MAX_EVENTS {
+            events.pop_front();
+        }
+        events.push_back(Event {
+            name,
+            start_us: start.saturating_duration_since(*epoch).as_micros(),
+            duration_us: duration.as_micros(),
+        });
+    }
+}
+
+/// Timings of every span recorded so far, by name. Empty unless spans are being recorded.
+pub fn stats() -> Vec<(String, Stats)> {
+    STATS
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(name, stats)| (name.to_string(), *stats))
+        .collect()
+}
+
+/// Starts collecting spans to be written to `path` as a Chrome trace by [`finish`].
+pub fn enable_chrome(path: &Path) {
+    EPOCH.get_or_init(Instant::now);
+    if CHROME_PATH.set(path.to_path_buf()).is_ok() {
+        CHROME_ENABLED.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Logs the span timings and writes the collected spans out, if a Chrome trace was requested.
+pub fn finish() {
+    for (name, stats) in stats() {
+        log::info!(
+            "{}: {} spans, mean {:.3}ms, max {:.3}ms",
+            name,
+            stats.count,
+            stats.mean().as_secs_f64() * 1000.0,
+            stats.max.as_secs_f64() * 1000.0
+        );
+    }
+    let Some(path) = CHROME_PATH.get() else {
+        return;
+    };
+    CHROME_ENABLED.store(false, Ordering::Relaxed);
+    let events = std::mem::take(&mut *EVENTS.lock().unwrap());
+    if let Err(err) = write_chrome(path, &events) {
+        log::error!(
+            "Failed to write chrome trace to {}: {}",
+            path.display(),
+            err
+        );
+    } else {
+        log::info!("Wrote {} spans to {}", events.len(), path.display());
+    }
+}
+
+/// An event in the Chrome trace format; only complete ("X") events are written.
+#[derive(Serialize)]
+struct ChromeEvent<'a> {
+    name: &'a str,
+    ph: &'static str,
+    ts: u128,
+    dur: u128,
+    pid: u32,
+    tid: u32,
+}
+
+#[derive(Serialize)]
+struct ChromeTrace<'a> {
+    #[serde(rename = "traceEvents")]
+    trace_events: Vec<ChromeEvent<'a>>,
+}
+
+fn write_chrome(path: &Path, events: &VecDeque<Event>) -> std::io::Result<()> {
+    let trace = ChromeTrace {
+        trace_events: events
+            .iter()
+            .map(|event| ChromeEvent {
+                name: &event.name,
+                ph: "X",
+                ts: event.start_us,
+                dur: event.duration_us,
+                pid: std::process::id(),
+                tid: 1,
+            })
+            .collect(),
+    };
+    let mut out = BufWriter::new(File::create(path)?);
+    serde_json::to_writer(&mut out, &trace)?;
+    writeln!(out)?;
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stats_sum_up_spans() {
+        let mut stats = Stats::default();
+        assert_eq!(stats.mean(), Duration::ZERO);
+        for ms in [2, 4, 9] {
+            stats.add(Duration::from_millis(ms));
+        }
+        assert_eq!(stats.count, 3);
+        assert_eq!(stats.mean(), Duration::from_millis(5));
+        assert_eq!(stats.max, Duration::from_millis(9));
+    }
+}
@@ -0,0 +1,34 @@
+// The library is usable without a compositor up to the point of showing the wallpaper.
+use lively_rs::config::CHANNEL_COUNT;
+use lively_rs::{Config, Settings};
+use std::path::PathBuf;
+
+#[test]
+fn settings_default_to_showing_the_embedded_shader() {
+    let settings = Settings::default();
+    assert!(!settings.list_outputs);
+    assert!(settings.tracing_chrome.is_none());
+    assert!(settings.config.shader.is_none());
+    assert!(settings.config.image.is_none());
+    assert_eq!(settings.config.fps, 0);
+}
+
+#[test]
+fn settings_are_built_from_a_config() {
+    let settings = Settings {
+        config: Config {
+            shader: Some(PathBuf::from("shaders/mouse-glow.wgsl")),
+            fps: 30,
+            keyboard_channel: Some(CHANNEL_COUNT - 1),
+            ..Config::default()
+        },
+        tracing_chrome: Some(PathBuf::from("trace.json")),
+        ..Settings::default()
+    };
+    assert_eq!(settings.config.fps, 30);
+    assert_eq!(settings.config.keyboard_channel, Some(CHANNEL_COUNT - 1));
+    assert_eq!(
+        settings.config.shader.as_deref(),
+        Some("shaders/mouse-glow.wgsl".as_ref())
+    );
+}